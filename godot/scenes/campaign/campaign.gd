@@ -0,0 +1,27 @@
+extends Node2D
+
+@onready var _levels = $ScrollContainer/Levels as VBoxContainer
+
+func _ready() -> void:
+	var campaign: Campaign = Global.campaign()
+	for pack in range(campaign.pack_count()):
+		var title := Label.new()
+		title.text = campaign.pack_name(pack)
+		_levels.add_child(title)
+		for level in range(campaign.level_count(pack)):
+			var button := Button.new()
+			button.text = campaign.level_name(pack, level)
+			if campaign.is_completed(pack, level):
+				button.text += '(已完成 %d秒)' % int(campaign.best_time(pack, level))
+			button.disabled = !campaign.is_unlocked(pack, level)
+			button.pressed.connect(self._on_level_pressed.bind(pack, level))
+			_levels.add_child(button)
+	print('闯关模式')
+
+func _on_level_pressed(pack: int, level: int) -> void:
+	Global.config.campaign_level = Vector2i(pack, level)
+	get_tree().change_scene_to_file('res://scenes/in_game/in_game_scenes.tscn')
+
+func _on_back_button_pressed() -> void:
+	Global.config.campaign_level = Vector2i(-1, -1)
+	get_tree().change_scene_to_file('res://scenes/main/main.tscn')
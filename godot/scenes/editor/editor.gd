@@ -0,0 +1,62 @@
+extends Node2D
+
+const BOARD_PADDING: float = 40.0
+# 导出的谜题保存在这个目录
+const EXPORT_DIR: String = 'user://puzzles'
+
+func _ready() -> void:
+	var board: Rect2 = $GameMap.board_rect()
+	board.position += $GameMap.position
+	$SimpleZoomCamera2D.fit_rect(board, BOARD_PADDING)
+	# 求解可能比较慢，合并同一帧中的多次修改
+	$GameMap.puzzle_edited.connect(self.refresh_status, CONNECT_DEFERRED)
+	self.refresh_status()
+
+func refresh_status() -> void:
+	var check: Dictionary = $GameMap.editor_check()
+	$CanvasLayer/StatusLabel.text = check.message
+	$CanvasLayer/PlayButton.disabled = !check.unique
+
+func _on_island_button_pressed() -> void:
+	$GameMap.editor_tool = 'island'
+
+func _on_obstacle_button_pressed() -> void:
+	$GameMap.editor_tool = 'obstacle'
+
+func _on_erase_button_pressed() -> void:
+	$GameMap.editor_tool = 'erase'
+
+func _on_derive_button_pressed() -> void:
+	$GameMap.editor_derive_clues()
+
+func _on_export_text_button_pressed() -> void:
+	self.export_puzzle($GameMap.to_puzzle_text(), 'txt')
+
+func _on_export_json_button_pressed() -> void:
+	self.export_puzzle($GameMap.to_puzzle_json(), 'json')
+
+# 复制到剪贴板并且保存到EXPORT_DIR
+func export_puzzle(text: String, extension: String) -> void:
+	DisplayServer.clipboard_set(text)
+	DirAccess.make_dir_recursive_absolute(EXPORT_DIR)
+	var path: String = '%s/puzzle_%d.%s' % [EXPORT_DIR, int(Time.get_unix_time_from_system()), extension]
+	var file: FileAccess = FileAccess.open(path, FileAccess.WRITE)
+	if file == null:
+		push_error('保存谜题失败，信息：' + error_string(FileAccess.get_open_error()))
+		return
+	file.store_string(text)
+	$CanvasLayer/StatusLabel.text = '已复制并保存到' + path
+
+func _on_play_button_pressed() -> void:
+	Global.config.puzzle_text = $GameMap.to_puzzle_text()
+	Global.config.from_editor = true
+	get_tree().change_scene_to_file('res://scenes/in_game/in_game_scenes.tscn')
+
+func _on_new_button_pressed() -> void:
+	Global.config.puzzle_text = ''
+	get_tree().reload_current_scene()
+
+func _on_back_button_pressed() -> void:
+	Global.config.puzzle_text = ''
+	Global.config.from_editor = false
+	get_tree().change_scene_to_file('res://scenes/main/main.tscn')
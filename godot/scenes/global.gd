@@ -3,8 +3,33 @@ extends Node
 class_name Global
 
 static var config: Cfg = Cfg.new()
+# 保存在user://中的设置，修改后调用save保存
+static var settings: Settings = Settings.load_saved()
+# 闯关模式的关卡包，第一次使用时加载
+static var _campaign: Campaign = null
+# 联网对战的连接，切换场景之后继续使用
+static var _net: NetClient = null
+
+static func campaign() -> Campaign:
+	if _campaign == null:
+		_campaign = Campaign.new()
+		var err: String = _campaign.load_pack_dir('res://levels')
+		if err.length() > 0:
+			push_error('加载关卡包失败，信息：' + err)
+	return _campaign
+
+static func net() -> NetClient:
+	if _net == null:
+		_net = NetClient.new()
+	return _net
+
+# 只在本次运行中有效的配置，持久的设置见Settings
 class Cfg:
-	var map_size: Vector2i = Vector2i(10, 7)
-	var game_mode: int = 1
-	var zoom_speed: float = -0.1
-	var map_item_scale: float = 30.0
+	var map_mask: String = '' # 地图形状文件(.txt或者图片)，为空时是矩形地图
+	var daily_date: String = '' # 非空时为这一天的每日谜题，忽略其他地图设置
+	var puzzle_text: String = '' # 非空时加载谜题文本而不是随机生成
+	var campaign_level: Vector2i = Vector2i(-1, -1) # 闯关模式的(关卡包, 关卡)，x小于0时不是闯关模式
+	var from_editor: bool = false # 从编辑器试玩puzzle_text，返回时回到编辑器
+	var replay_text: String = '' # 回放场景播放的录像，见Replay
+	var hot_seat: bool = false # 两个玩家在同一张地图上轮流搭桥
+	var net_versus: bool = false # 联网对战，地图参数见Global.net()
@@ -1,108 +1,160 @@
 extends GameMap
 
-var pos_scale: float = Global.config.map_item_scale
+var pos_scale: float = Global.settings.get_setting('map_item_scale')
 var base_offset = Vector2(pos_scale/2, pos_scale/2)
-const PREVIEW_NAME: String = 'preview'
+const BRIDGE_ACTION_COUNT: Dictionary = {'Single': 1, 'Double': 2, 'Triple': 3}
+# 彩色岛屿变体中每种颜色的岛屿颜色
+const ISLAND_COLORS: Array[Color] = [
+	Color(1, 1, 1), Color(1, 0.6, 0.6), Color(0.6, 0.8, 1), Color(1, 0.9, 0.5), Color(0.7, 1, 0.7),
+]
+const SELECTED_COLOR: Color = Color(1, 0.85, 0.3)
+# 只显示地图，不响应玩家操作，用于显示联网对战中对手的进度
+@export var read_only: bool = false
+# 编辑器的工具：island放置岛屿，obstacle放置障碍物，erase移除岛屿或者障碍物
+var editor_tool: String = 'island'
+# 编辑器中选中的岛屿，按数字键设置需要的桥梁数量，按?隐藏数量
+var selected_island: Island = null
 
 # Called when the node enters the scene tree for the first time.
 func _ready() -> void:
 	self.connect('render_bridge', self.on_render_bridge)
-	self.width = Global.config.map_size.x
-	self.height = Global.config.map_size.y
-	self.game_mode = Global.config.game_mode
-	self.reset()
-	while !self.is_ready:
-		var exit_str: String = self.gen_island()
-		if exit_str.length() > 0:
-			push_error('生成结束，信息：' + exit_str)
-			break
-		#print('able_to_gen_islands', self.able_to_gen_islands)
-		#print('bridge_points', self.bridge_points)
+	self.use_settings(Global.settings)
+	if self.editor_mode:
+		self.setup_editor()
+	else:
+		self.setup_puzzle()
 	print('island计数' + String.num(self.islands.size()))
 	for child: Island in self.islands.values():
-		child.connect('finish_preview_bridge', self.on_finish_preview_bridge)
-		child.connect('preview_bridge', self.on_preview_bridge)
-		child.connect('change_bridge_count', self.on_change_bridge_count)
-		child.text = String.num(child.max_bridge_count)
-		#child.text = String.num(child.max_bridge_count) + "(" + String.num(child.pos.x) + "," + String.num(child.pos.y) + ")"
-		var size = Vector2(pos_scale, pos_scale)
-		child.set_size(size)
-		child.pivot_offset = base_offset
-		child.set_position(child.pos * pos_scale)
-		add_child(child)
+		self.setup_island(child)
+
+# 按照Global.config加载或者生成谜题
+func setup_puzzle() -> void:
+	if Global.config.hot_seat:
+		self.player_count = 2
+		self.connect('island_claimed', self.on_island_claimed)
+	if Global.config.map_mask.length() > 0:
+		self.load_mask(Global.config.map_mask)
+	if Global.config.daily_date.length() > 0:
+		var err: String = DailyPuzzle.setup_map(self, Global.config.daily_date)
+		if err.length() > 0:
+			push_error('每日谜题设置失败，信息：' + err)
+	elif Global.config.net_versus:
+		var err: String = Global.net().setup_map(self)
+		if err.length() > 0:
+			push_error('联网对战设置失败，信息：' + err)
+	elif Global.config.campaign_level.x >= 0:
+		var level: Vector2i = Global.config.campaign_level
+		var err: String = Global.campaign().setup_map(self, level.x, level.y)
+		if err.length() > 0:
+			push_error('关卡设置失败，信息：' + err)
+	elif Global.config.puzzle_text.length() > 0:
+		var err: String = self.load_puzzle_text(Global.config.puzzle_text)
+		if err.length() > 0:
+			push_error('加载谜题失败，信息：' + err)
+	# 加载谜题之后地图已经生成好了
+	if !self.is_ready:
+		var exit_str: String = self.generate()
+		if exit_str.length() > 0:
+			push_error('生成结束，信息：' + exit_str)
+
+# 编辑器从空地图开始，或者继续编辑试玩过的谜题
+func setup_editor() -> void:
+	if Global.config.puzzle_text.length() > 0:
+		var err: String = self.load_puzzle_text(Global.config.puzzle_text)
+		if err.length() > 0:
+			push_error('加载谜题失败，信息：' + err)
+	if !self.is_ready:
+		self.editor_new()
+
+func setup_island(island: Island) -> void:
+	island.connect('finish_preview_bridge', self.on_finish_preview_bridge)
+	island.connect('preview_bridge', self.on_preview_bridge)
+	island.connect('change_bridge_count', self.on_change_bridge_count)
+	island.text = '?' if island.clue_hidden else String.num(island.max_bridge_count)
+	#island.text = String.num(island.max_bridge_count) + "(" + String.num(island.pos.x) + "," + String.num(island.pos.y) + ")"
+	var size = Vector2(pos_scale, pos_scale)
+	island.set_size(size)
+	island.pivot_offset = base_offset
+	if self.color_count > 1:
+		island.self_modulate = ISLAND_COLORS[island.color_id % ISLAND_COLORS.size()]
+	island.set_position(self.cell_position(island.pos))
+	add_child(island)
+
+# 从文本或者图片加载地图形状
+func load_mask(path: String) -> void:
+	var err: String
+	if path.get_extension() == 'txt':
+		err = self.set_mask_text(FileAccess.get_file_as_string(path))
+	else:
+		err = self.set_mask_image(Image.load_from_file(path))
+	if err.length() > 0:
+		push_error('加载地图形状失败，信息：' + err)
 
 func _draw() -> void:
-	var sc = base_offset * 2
-	var map_size: Vector2i = Global.config.map_size
 	var color: Color = Color(1, 1, 1, 0.1)
-	for x in range(0, map_size.x):
-		draw_line(Vector2(x, 0) * sc + base_offset, Vector2(x, map_size.y - 1) * sc + base_offset, color, 5)
-	for y in range(0, map_size.y):
-		draw_line(Vector2(0, y) * sc + base_offset, Vector2(map_size.x - 1, y) * sc + base_offset, color, 5)
+	var lines: PackedVector2Array = self.grid_lines()
+	for i in range(0, lines.size(), 2):
+		draw_line(lines[i], lines[i + 1], color, 5)
+	var obstacle_color: Color = Color(0.45, 0.4, 0.35)
+	var cell_size = Vector2(pos_scale, pos_scale)
+	for p: Vector2i in self.obstacle_positions():
+		draw_rect(Rect2(self.cell_position(p), cell_size).grow(-pos_scale * 0.15), obstacle_color)
 
 func on_preview_bridge(island: Island, rel_pos: Vector2):
-	var abs_pos = rel_pos + island.position
-	var line = self.find_child(PREVIEW_NAME, false, false)
-	if line == null:
-		line = Line2D.new()
-		line.name = PREVIEW_NAME
-		line.width = 3.0
-		line.add_point(island.position + base_offset)
-		line.add_point(abs_pos)
-		self.add_child(line)
-	line = line as Line2D
-	if island.get_rect().has_point(abs_pos):
-		self.remove_child(line)
-		line.queue_free()
+	if self.read_only:
 		return
-	var offset_x: float = 0.0
-	var offset_y: float = 0.0
-	rel_pos -= base_offset
-	if abs(rel_pos.x) > pos_scale:
-		offset_x = rel_pos.x
-	if abs(rel_pos.y) > pos_scale:
-		offset_y = rel_pos.y
-	if abs(offset_x) >= abs(offset_y):
-		offset_y = 0.0
-		var n = offset_x / abs(offset_x)
-		line.set_point_position(0, island.position + base_offset + Vector2(n * pos_scale / 2, 0))
-	else:
-		offset_x = 0.0
-		var n = offset_y / abs(offset_y)
-		line.set_point_position(0, island.position + base_offset + Vector2(0, n * pos_scale / 2))
-	line.set_point_position(1, island.position + base_offset + Vector2(offset_x, offset_y))
+	self.show_preview(island.pos, rel_pos, island.is_sub_drag)
 
 func on_finish_preview_bridge(island: Island, rel_pos: Vector2):
-	var p = self.find_child(PREVIEW_NAME, false, false)
-	if p == null:
-		print('取消搭桥')
+	if self.read_only:
 		return
-	print('开始搭桥')
-	self.remove_child(p)
-	p.queue_free()
-	print('gd端创建桥梁', island, rel_pos)
-	var offset_x: float = 0.0
-	var offset_y: float = 0.0
-	rel_pos -= base_offset
-	if abs(rel_pos.x) > pos_scale:
-		offset_x = rel_pos.x
-	if abs(rel_pos.y) > pos_scale:
-		offset_y = rel_pos.y
-	if abs(offset_x) >= abs(offset_y):
-		var n = offset_x / abs(offset_x)
-		if n > 0:
-			self.user_gen_bridge(island.pos, 'Right')
-		else:
-			self.user_gen_bridge(island.pos, 'Left')
+	var action: String = self.finish_preview(island.pos, rel_pos, island.is_sub_drag)
+	print('gd端创建桥梁', island, rel_pos, action)
+	# 编辑器中在岛屿上松开视为点击岛屿
+	if self.editor_mode and action == 'Pass' and Rect2(Vector2.ZERO, island.size).has_point(rel_pos):
+		self.editor_click_island(island)
+
+func _unhandled_input(event: InputEvent) -> void:
+	if !self.editor_mode:
+		return
+	if event is InputEventMouseButton and event.pressed and event.button_index == MOUSE_BUTTON_LEFT:
+		self.editor_click_cell(self.cell_at(get_local_mouse_position()))
+	elif event is InputEventKey and event.pressed and self.selected_island != null:
+		if event.keycode >= KEY_0 and event.keycode <= KEY_9:
+			self.editor_set_clue(self.selected_island.pos, event.keycode - KEY_0)
+		elif event.unicode == '?'.unicode_at(0):
+			self.editor_set_clue(self.selected_island.pos, -1)
+
+# 编辑器中点击没有岛屿的格子
+func editor_click_cell(cell: Vector2i) -> void:
+	if self.editor_tool == 'island':
+		var island: Island = self.editor_toggle_island(cell)
+		if island != null:
+			self.setup_island(island)
+			self.select_island(island)
+	elif self.editor_tool == 'obstacle' or cell in self.obstacle_positions():
+		if self.editor_toggle_obstacle(cell):
+			queue_redraw()
+
+func editor_click_island(island: Island) -> void:
+	if self.editor_tool == 'erase':
+		if island == self.selected_island:
+			self.select_island(null)
+		self.editor_toggle_island(island.pos)
 	else:
-		var n = offset_y / abs(offset_y)
-		if n > 0:
-			self.user_gen_bridge(island.pos, 'Down')
-		else:
-			self.user_gen_bridge(island.pos, 'Up')
+		self.select_island(island)
+
+func select_island(island: Island) -> void:
+	if self.selected_island != null:
+		self.selected_island.modulate = Color(1, 1, 1)
+	self.selected_island = island
+	if island != null:
+		island.modulate = SELECTED_COLOR
 
 func on_change_bridge_count(island: Island):
-	if island.max_bridge_count == island.current_bridge_count:
+	# 编辑器中需要的桥梁数量会改变
+	island.text = '?' if island.clue_hidden else String.num(island.max_bridge_count)
+	if !island.clue_hidden and island.max_bridge_count == island.current_bridge_count:
 		var color = Color(0, 0.7, 0)
 		island.add_theme_color_override('font_focus_color', color)
 		island.add_theme_color_override('font_hover_color', color)
@@ -114,13 +166,10 @@ func on_change_bridge_count(island: Island):
 		island.remove_theme_color_override('font_color')
 		#island.text = String.num(island.max_bridge_count)
 
-func on_render_bridge(src_island: Island, target_island: Island, action: String):
-	var i_arr: Array[Island] = Bridge.order_island(src_island, target_island)
-	src_island = i_arr[0]
-	target_island = i_arr[1]
+func on_render_bridge(src_island: Island, target_island: Island, action: String, direction: String):
 	var p1 = src_island.pos
 	var p2 = target_island.pos
-	var bridge_name: String = Bridge.calc_name(p1, p2)
+	var bridge_name: String = self.bridge_name(p1, p2, direction)
 	var bridge_node: Bridge = self.find_child(bridge_name, false, false)
 	if action == 'Remove':
 		if bridge_node != null:
@@ -129,9 +178,25 @@ func on_render_bridge(src_island: Island, target_island: Island, action: String)
 		return
 	if bridge_node == null:
 		# action == 'Single'
-		bridge_node = Bridge.create(p1, p2, pos_scale, 1)
-		bridge_node.position = src_island.position
+		bridge_node = self.create_bridge(p1, p2, direction, 1)
+		bridge_node.connect('bridge_clicked', self.on_bridge_clicked)
+		bridge_node.connect('bridge_right_clicked', self.on_bridge_right_clicked)
 		self.add_child(bridge_node)
-	if action == 'Double':
-		print('双桥')
-		(bridge_node as Bridge).change_bridge_count(2)
+	(bridge_node as Bridge).change_bridge_count(BRIDGE_ACTION_COUNT[action])
+	if self.player_count > 1:
+		(bridge_node as Bridge).set_player(self.bridge_owner(p1, direction))
+
+# 对战模式中用玩家的颜色标记完成的岛屿
+func on_island_claimed(pos: Vector2i, player: int):
+	var island: Island = self.islands[pos]
+	island.modulate = Color(1, 1, 1) if player < 0 else Bridge.player_color(player)
+
+func on_bridge_clicked(p: Vector2i, direction: String):
+	if self.read_only:
+		return
+	self.user_gen_bridge(p, direction)
+
+func on_bridge_right_clicked(p: Vector2i, direction: String):
+	if self.read_only:
+		return
+	self.user_sub_bridge(p, direction)
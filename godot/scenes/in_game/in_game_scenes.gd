@@ -1,15 +1,104 @@
 extends Node2D
 
+const BOARD_PADDING: float = 40.0
+# 完成的录像保存在这个目录，见回放列表
+const REPLAY_DIR: String = 'user://replays'
+
+# Stats需要在GameMap生成地图之前知道profile和是否记录
+func _enter_tree() -> void:
+	$Stats.profile = Global.settings.get_setting('profile')
+	$Stats.recording = is_normal_game()
+
+# 随机生成的单人游戏，只有这种游戏计入统计
+func is_normal_game() -> bool:
+	var config := Global.config
+	return !config.hot_seat and !config.net_versus and !config.from_editor \
+		and config.daily_date.length() == 0 and config.campaign_level.x < 0 \
+		and config.puzzle_text.length() == 0
+
 func _ready() -> void:
-	#var offset = Global.config.map_size * Global.config.map_item_scale / 2
-	var offset = Vector2(50, 50)
-	$SimpleZoomCamera2D.position -= offset
+	var board: Rect2 = $GameMap.board_rect()
+	board.position += $GameMap.position
+	$SimpleZoomCamera2D.fit_rect(board, BOARD_PADDING)
+	$SimpleZoomCamera2D.limit_to_rect(board)
+	$GameSession.time_limit = Global.settings.get_setting('time_limit')
+	$GameSession.start()
+	if $GameMap.player_count > 1:
+		$GameMap.turn_changed.connect(self.update_turn_label.unbind(1))
+		$GameMap.island_claimed.connect(self.update_turn_label.unbind(2))
+		$CanvasLayer/UndoButton.disabled = true
+		$CanvasLayer/HintButton.disabled = true
+		$CanvasLayer/TurnLabel.visible = true
+		self.update_turn_label()
+
+func update_turn_label() -> void:
+	var player: int = $GameMap.current_player()
+	var label: Label = $CanvasLayer/TurnLabel
+	label.text = '玩家%d回合  %d : %d' % [player + 1, $GameMap.player_score(0), $GameMap.player_score(1)]
+	label.add_theme_color_override('font_color', Bridge.player_color(player))
+
+func _process(_delta: float) -> void:
+	var seconds: float = $GameSession.elapsed
+	if $GameSession.time_limit > 0:
+		seconds = $GameSession.remaining_time()
+	$CanvasLayer/TimeLabel.text = '%d秒' % int(seconds)
+
+func _on_game_session_session_finished(score: int) -> void:
+	$CanvasLayer/SolvedLabel.text = '完成！分数：%d' % score
+	if $GameMap.player_count > 1:
+		var winner: int = $GameMap.winner()
+		$CanvasLayer/SolvedLabel.text = '平局！' if winner < 0 else '玩家%d获胜！' % (winner + 1)
+	if Global.config.daily_date.length() > 0:
+		DailyPuzzle.record_result(Global.config.daily_date, $GameSession.elapsed)
+	elif Global.config.campaign_level.x >= 0:
+		var level: Vector2i = Global.config.campaign_level
+		Global.campaign().record_result(level.x, level.y, $GameSession.elapsed)
+	$CanvasLayer/SolvedLabel.visible = true
+	self.save_replay()
+	$CanvasLayer/ReplayButton.visible = true
+
+func save_replay() -> void:
+	DirAccess.make_dir_recursive_absolute(REPLAY_DIR)
+	var path: String = '%s/%d.txt' % [REPLAY_DIR, int(Time.get_unix_time_from_system())]
+	var err: String = $Replay.save_to(path)
+	if err.length() > 0:
+		push_error('保存录像失败，信息：' + err)
+
+func _on_replay_button_pressed() -> void:
+	Global.config.replay_text = $Replay.to_text()
+	get_tree().paused = false
+	get_tree().change_scene_to_file('res://scenes/replay/replay.tscn')
+
+func _on_game_session_time_up() -> void:
+	$CanvasLayer/SolvedLabel.text = '超时'
+	$CanvasLayer/SolvedLabel.visible = true
+
+func _on_undo_button_pressed() -> void:
+	$GameMap.undo()
+
+func _on_hint_button_pressed() -> void:
+	$GameMap.hint()
+
+func _on_pause_button_pressed() -> void:
+	if $GameSession.is_paused():
+		$GameSession.resume()
+		$CanvasLayer/PauseButton.text = '暂停'
+	else:
+		$GameSession.pause()
+		$CanvasLayer/PauseButton.text = '继续'
 
 func _on_back_button_pressed() -> void:
-	get_tree().change_scene_to_file('res://scenes/main/main.tscn')
+	get_tree().paused = false
+	if Global.config.campaign_level.x >= 0:
+		get_tree().change_scene_to_file('res://scenes/campaign/campaign.tscn')
+	elif Global.config.from_editor:
+		get_tree().change_scene_to_file('res://scenes/editor/editor.tscn')
+	else:
+		get_tree().change_scene_to_file('res://scenes/main/main.tscn')
 
 func _on_renew_button_pressed() -> void:
 	self.reset()
 
 func reset():
+	get_tree().paused = false
 	get_tree().change_scene_to_file('res://scenes/in_game/in_game_scenes.tscn')
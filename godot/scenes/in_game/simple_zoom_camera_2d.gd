@@ -1,4 +1,4 @@
 extends SimpleZoomCamera2D
 
 func _ready() -> void:
-	self.zoom_speed = Global.config.zoom_speed
+	self.use_settings(Global.settings)
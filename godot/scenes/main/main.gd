@@ -6,15 +6,59 @@ extends Node2D
 var blank_reg = RegEx.new()
 
 func _ready() -> void:
-	_width_edit.text = String.num(Global.config.map_size.x)
-	_height_edit.text = String.num(Global.config.map_size.y)
-	_game_mode.text = String.num(Global.config.game_mode)
+	var map_size: Vector2i = Global.settings.get_setting('map_size')
+	_width_edit.text = String.num(map_size.x)
+	_height_edit.text = String.num(map_size.y)
+	_game_mode.text = String.num(Global.settings.get_setting('game_mode'))
 	blank_reg.compile('\\s')
+	var today: String = DailyPuzzle.today()
+	if DailyPuzzle.is_solved(today):
+		$DailyButton.text = '每日谜题(已完成 %d秒)' % int(DailyPuzzle.best_time(today))
 	print('游戏主菜单')
 
 func _on_start_button_pressed() -> void:
+	self.start_random_game(false)
+
+# 两个玩家在随机生成的地图上轮流搭桥
+func _on_hot_seat_button_pressed() -> void:
+	self.start_random_game(true)
+
+func _on_net_button_pressed() -> void:
+	get_tree().change_scene_to_file('res://scenes/net/net_lobby.tscn')
+
+func start_random_game(hot_seat: bool) -> void:
+	Global.config.daily_date = ''
+	Global.config.campaign_level = Vector2i(-1, -1)
+	Global.config.puzzle_text = ''
+	Global.config.from_editor = false
+	Global.config.hot_seat = hot_seat
 	get_tree().change_scene_to_file('res://scenes/in_game/in_game_scenes.tscn')
 
+func _on_daily_button_pressed() -> void:
+	Global.config.daily_date = DailyPuzzle.today()
+	Global.config.campaign_level = Vector2i(-1, -1)
+	Global.config.from_editor = false
+	Global.config.hot_seat = false
+	get_tree().change_scene_to_file('res://scenes/in_game/in_game_scenes.tscn')
+
+func _on_campaign_button_pressed() -> void:
+	Global.config.daily_date = ''
+	Global.config.hot_seat = false
+	get_tree().change_scene_to_file('res://scenes/campaign/campaign.tscn')
+
+func _on_stats_button_pressed() -> void:
+	get_tree().change_scene_to_file('res://scenes/stats/stats.tscn')
+
+func _on_editor_button_pressed() -> void:
+	Global.config.daily_date = ''
+	Global.config.campaign_level = Vector2i(-1, -1)
+	Global.config.puzzle_text = ''
+	Global.config.hot_seat = false
+	get_tree().change_scene_to_file('res://scenes/editor/editor.tscn')
+
+func _on_replay_button_pressed() -> void:
+	get_tree().change_scene_to_file('res://scenes/replay/replay_list.tscn')
+
 func _on_settings_button_pressed() -> void:
 	get_tree().change_scene_to_file('res://scenes/settings/settings.tscn')
 
@@ -22,16 +66,13 @@ func _on_exit_button_pressed() -> void:
 	get_tree().quit(0)
 
 func _on_width_edit_focus_exited() -> void:
-	var t: String = _width_edit.text
-	var w: int
-	if t == null or t.is_empty() or !t.is_valid_int():
-		w = 10
-	else:
-		w = t.to_int()
-		if w < 5:
-			w = 5
-	Global.config.map_size.x = w
-	_width_edit.text = String.num(w)
+	var map_size: Vector2i = Global.settings.get_setting('map_size')
+	if _width_edit.text.is_valid_int():
+		map_size.x = _width_edit.text.to_int()
+	# 超出范围的值由Settings修正
+	map_size = Global.settings.set_setting('map_size', map_size)
+	Global.settings.save()
+	_width_edit.text = String.num(map_size.x)
 
 func _on_width_edit_text_changed() -> void:
 	var t: String = _width_edit.text
@@ -44,16 +85,12 @@ func _on_width_edit_text_changed() -> void:
 		_width_edit.text = n
 
 func _on_height_edit_focus_exited() -> void:
-	var t: String = _height_edit.text
-	var h: int
-	if t == null or t.is_empty() or !t.is_valid_int():
-		h = 7
-	else:
-		h = t.to_int()
-		if h < 5:
-			h = 5
-	Global.config.map_size.y = h
-	_height_edit.text = String.num(h)
+	var map_size: Vector2i = Global.settings.get_setting('map_size')
+	if _height_edit.text.is_valid_int():
+		map_size.y = _height_edit.text.to_int()
+	map_size = Global.settings.set_setting('map_size', map_size)
+	Global.settings.save()
+	_height_edit.text = String.num(map_size.y)
 
 func _on_height_edit_text_changed() -> void:
 	var t: String = _height_edit.text
@@ -66,17 +103,11 @@ func _on_height_edit_text_changed() -> void:
 		_height_edit.text = n
 
 func _on_game_mode_edit_focus_exited() -> void:
-	var t: String = _game_mode.text
-	var v: int
-	if t == null or t.is_empty() or !t.is_valid_int():
-		v = 1
-	else:
-		v = t.to_int()
-		if v < 1:
-			v = 5
-		elif v > 20:
-			v = 20
-	Global.config.game_mode = v
+	var v: int = Global.settings.get_setting('game_mode')
+	if _game_mode.text.is_valid_int():
+		v = _game_mode.text.to_int()
+	v = Global.settings.set_setting('game_mode', v)
+	Global.settings.save()
 	_game_mode.text = String.num(v)
 
 func _on_game_mode_edit_text_changed() -> void:
@@ -0,0 +1,50 @@
+extends Node2D
+
+func _ready() -> void:
+	$HostEdit.text = '127.0.0.1'
+	$PortEdit.text = String.num(NetClient.default_port())
+	var net: NetClient = Global.net()
+	net.welcome.connect(self._on_welcome)
+	net.opponent_joined.connect(self._on_opponent_joined)
+	net.board_received.connect(self._on_board_received.unbind(4))
+	net.disconnected.connect(self._on_disconnected)
+	print('联网对战')
+
+func _process(_delta: float) -> void:
+	Global.net().poll()
+
+func _on_connect_button_pressed() -> void:
+	if !$PortEdit.text.is_valid_int():
+		$StatusLabel.text = '端口格式错误'
+		return
+	var err: String = Global.net().connect_to($HostEdit.text, $PortEdit.text.to_int(), Global.settings.get_setting('profile'))
+	$StatusLabel.text = err if err.length() > 0 else '正在连接……'
+
+func _on_welcome(_player: int) -> void:
+	$StatusLabel.text = '已连接，等待对手加入'
+
+# 玩家0按照自己的设置选择地图
+func _on_opponent_joined(opponent_name: String) -> void:
+	$StatusLabel.text = '对手：' + opponent_name
+	if Global.net().player() == 0:
+		var map_size: Vector2i = Global.settings.get_setting('map_size')
+		Global.net().send_board(randi(), map_size.x, map_size.y, Global.settings.get_setting('game_mode'))
+		self.start_game()
+
+func _on_board_received() -> void:
+	self.start_game()
+
+func start_game() -> void:
+	Global.config.daily_date = ''
+	Global.config.campaign_level = Vector2i(-1, -1)
+	Global.config.puzzle_text = ''
+	Global.config.hot_seat = false
+	Global.config.net_versus = true
+	get_tree().change_scene_to_file('res://scenes/net/net_versus.tscn')
+
+func _on_disconnected() -> void:
+	$StatusLabel.text = '连接断开'
+
+func _on_back_button_pressed() -> void:
+	Global.net().close()
+	get_tree().change_scene_to_file('res://scenes/main/main.tscn')
@@ -0,0 +1,53 @@
+extends Node2D
+
+const BOARD_PADDING: float = 40.0
+# 对手完成的用时，-1表示还没有完成
+var opponent_seconds: float = -1
+
+func _ready() -> void:
+	var board: Rect2 = $GameMap.board_rect()
+	board.position += $GameMap.position
+	$SimpleZoomCamera2D.fit_rect(board, BOARD_PADDING)
+	$SimpleZoomCamera2D.limit_to_rect(board)
+	var net: NetClient = Global.net()
+	$GameMap.user_moved.connect(net.on_user_moved)
+	net.set_opponent_map($CanvasLayer/OpponentMap)
+	net.opponent_moved.connect(self._on_opponent_moved)
+	net.opponent_finished.connect(self._on_opponent_finished)
+	net.opponent_left.connect(self._on_opponent_left)
+	net.disconnected.connect(self._on_opponent_left)
+	$CanvasLayer/OpponentLabel.text = '对手：' + net.opponent_name()
+	$GameSession.start()
+
+func _exit_tree() -> void:
+	Global.config.net_versus = false
+
+func _process(_delta: float) -> void:
+	Global.net().poll()
+	$CanvasLayer/TimeLabel.text = '%d秒' % int($GameSession.elapsed)
+
+# 对手的操作显示在小地图上
+func _on_opponent_moved(src_pos: Vector2i, direction: String, count: int) -> void:
+	$CanvasLayer/OpponentMap.user_set_bridge(src_pos, direction, count)
+
+func _on_opponent_finished(seconds: float) -> void:
+	opponent_seconds = seconds
+	$CanvasLayer/OpponentLabel.text = '对手：%s 完成 %d秒' % [Global.net().opponent_name(), int(seconds)]
+	if !$GameSession.is_finished:
+		$CanvasLayer/ResultLabel.text = '对手先完成了'
+		$CanvasLayer/ResultLabel.visible = true
+
+func _on_opponent_left() -> void:
+	$CanvasLayer/OpponentLabel.text = '对手已离开'
+
+func _on_game_session_session_finished(_score: int) -> void:
+	Global.net().send_finished($GameSession.elapsed)
+	if opponent_seconds < 0:
+		$CanvasLayer/ResultLabel.text = '你赢了！'
+	else:
+		$CanvasLayer/ResultLabel.text = '完成！用时%d秒' % int($GameSession.elapsed)
+	$CanvasLayer/ResultLabel.visible = true
+
+func _on_back_button_pressed() -> void:
+	Global.net().close()
+	get_tree().change_scene_to_file('res://scenes/main/main.tscn')
@@ -0,0 +1,61 @@
+extends Node2D
+
+const BOARD_PADDING: float = 40.0
+# 速度按钮循环切换的倍数
+const SPEEDS: Array[float] = [1.0, 2.0, 4.0, 0.5]
+var speed_index: int = 0
+
+# GameMap在_ready中按照puzzle_text加载录像中的谜题
+func _enter_tree() -> void:
+	var err: String = $Replay.load_text(Global.config.replay_text)
+	if err.length() > 0:
+		push_error('加载录像失败，信息：' + err)
+	Global.config.daily_date = ''
+	Global.config.campaign_level = Vector2i(-1, -1)
+	Global.config.hot_seat = false
+	Global.config.puzzle_text = $Replay.puzzle_text()
+
+func _ready() -> void:
+	Global.config.puzzle_text = ''
+	var board: Rect2 = $GameMap.board_rect()
+	board.position += $GameMap.position
+	$SimpleZoomCamera2D.fit_rect(board, BOARD_PADDING)
+	$SimpleZoomCamera2D.limit_to_rect(board)
+	$Replay.play()
+	self.update_play_button()
+
+func _process(_delta: float) -> void:
+	$CanvasLayer/ProgressLabel.text = '%d/%d  %.1f/%.1f秒' % [
+		$Replay.played_count(), $Replay.event_count(), $Replay.time, $Replay.duration()]
+
+func update_play_button() -> void:
+	$CanvasLayer/PlayButton.text = '暂停' if $Replay.is_playing() else '播放'
+
+func _on_play_button_pressed() -> void:
+	if $Replay.is_playing():
+		$Replay.pause()
+	else:
+		$Replay.play()
+	self.update_play_button()
+
+func _on_step_button_pressed() -> void:
+	$Replay.pause()
+	$Replay.step()
+	self.update_play_button()
+
+func _on_speed_button_pressed() -> void:
+	speed_index = (speed_index + 1) % SPEEDS.size()
+	$Replay.speed = SPEEDS[speed_index]
+	$CanvasLayer/SpeedButton.text = '%s倍' % String.num(SPEEDS[speed_index])
+
+func _on_restart_button_pressed() -> void:
+	get_tree().reload_current_scene()
+
+func _on_copy_button_pressed() -> void:
+	DisplayServer.clipboard_set(Global.config.replay_text)
+
+func _on_replay_playback_finished() -> void:
+	self.update_play_button()
+
+func _on_back_button_pressed() -> void:
+	get_tree().change_scene_to_file('res://scenes/replay/replay_list.tscn')
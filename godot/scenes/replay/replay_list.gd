@@ -0,0 +1,33 @@
+extends Node2D
+
+const REPLAY_DIR: String = 'user://replays'
+
+@onready var _replays = $ScrollContainer/Replays as VBoxContainer
+
+func _ready() -> void:
+	var files: PackedStringArray = DirAccess.get_files_at(REPLAY_DIR)
+	# 文件名是保存时的时间戳，最新的在前面
+	for i in range(files.size() - 1, -1, -1):
+		var file: String = files[i]
+		if file.get_extension() != 'txt':
+			continue
+		var button := Button.new()
+		var unix: int = file.get_basename().to_int()
+		button.text = Time.get_datetime_string_from_unix_time(unix, true)
+		button.pressed.connect(self._on_replay_pressed.bind(REPLAY_DIR + '/' + file))
+		_replays.add_child(button)
+	print('回放列表')
+
+func play(text: String) -> void:
+	Global.config.replay_text = text
+	get_tree().change_scene_to_file('res://scenes/replay/replay.tscn')
+
+func _on_replay_pressed(path: String) -> void:
+	self.play(FileAccess.get_file_as_string(path))
+
+# 播放别人分享的录像
+func _on_clipboard_button_pressed() -> void:
+	self.play(DisplayServer.clipboard_get())
+
+func _on_back_button_pressed() -> void:
+	get_tree().change_scene_to_file('res://scenes/main/main.tscn')
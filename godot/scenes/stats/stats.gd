@@ -0,0 +1,24 @@
+extends Node2D
+
+func _ready() -> void:
+	$Stats.profile = Global.settings.get_setting('profile')
+	var lines: PackedStringArray = []
+	lines.append('玩家：%s' % $Stats.profile)
+	lines.append('连续完成：%d(最多%d)' % [$Stats.current_streak(), $Stats.best_streak()])
+	lines.append(format_stats('合计', $Stats.total_stats()))
+	for i in range($Stats.group_count()):
+		var group: Dictionary = $Stats.group_stats(i)
+		var title: String = '%dx%d 难度%d' % [group.width, group.height, group.game_mode]
+		lines.append(format_stats(title, group))
+	$ScrollContainer/StatsLabel.text = '\n'.join(lines)
+
+func format_stats(title: String, stats: Dictionary) -> String:
+	var text: String = '%s：开始%d 完成%d 提示%d 错误%d' % [
+		title, stats.started, stats.finished, stats.hints, stats.mistakes,
+	]
+	if stats.best_time >= 0:
+		text += ' 最快%d秒 平均%d秒' % [int(stats.best_time), int(stats.average_time)]
+	return text
+
+func _on_back_button_pressed() -> void:
+	get_tree().change_scene_to_file('res://scenes/main/main.tscn')
@@ -0,0 +1,49 @@
+# 无界面试玩：用求解器机器人完成一批种子生成的地图，有失败时退出码为1
+# 用法：godot --headless --path godot -s tests/playtest.gd
+extends SceneTree
+
+const SEED_COUNT := 20
+# 宽、高、难度
+const CASES := [
+	[5, 5, 0],
+	[8, 6, 1],
+	[10, 7, 2],
+	[12, 9, 3],
+]
+
+func _init() -> void:
+	var playtest := Playtest.new()
+	var failed_count := 0
+	for c in CASES:
+		var failed: PackedInt64Array = playtest.check_seeds(c[0], c[1], c[2], 1, SEED_COUNT)
+		print('%dx%d 难度%d：%d/%d 通过' % [c[0], c[1], c[2], SEED_COUNT - failed.size(), SEED_COUNT])
+		if failed.size() > 0:
+			print('  失败的种子：', failed)
+		failed_count += failed.size()
+	if !check_missed_click(playtest):
+		failed_count += 1
+	quit(1 if failed_count > 0 else 0)
+
+# 脚本点击空白格子时应该报告无效的点击，而不是崩溃
+func check_missed_click(playtest: Playtest) -> bool:
+	var map: GameMap = playtest.generate(7, 7, 1, 3)
+	if map == null:
+		print('点击空白格子：生成失败')
+		return false
+	var cell := empty_cell(map.to_puzzle_text())
+	var res: Dictionary = playtest.run_script(map, '%d %d Up\n' % [cell.x, cell.y])
+	playtest.dispose(map)
+	var ok: bool = res.message.begins_with('第1次点击无效')
+	print('点击空白格子：', '通过' if ok else '失败 ' + str(res))
+	return ok
+
+# 谜题文本中第一个空白格子
+func empty_cell(text: String) -> Vector2i:
+	var lines := text.split('\n')
+	var y := lines.find('grid') + 1
+	for row in range(y, lines.size()):
+		var cells := lines[row].split(' ')
+		var x := cells.find('.')
+		if x >= 0:
+			return Vector2i(x, row - y)
+	return Vector2i(-1, -1)
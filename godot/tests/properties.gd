@@ -0,0 +1,19 @@
+# 生成器的性质测试：对多种大小和难度的一批种子检查生成的地图，见invariants.rs，有失败时退出码为1
+# 用法：godot --headless --path godot -s tests/properties.gd
+extends SceneTree
+
+const SEED_COUNT := 50
+const SIZES := [Vector2i(3, 3), Vector2i(5, 4), Vector2i(7, 7), Vector2i(10, 7), Vector2i(15, 12)]
+const GAME_MODES := [0, 1, 2, 3, 5]
+
+func _init() -> void:
+	var playtest := Playtest.new()
+	var failed_count := 0
+	for size in SIZES:
+		for game_mode in GAME_MODES:
+			var failed: PackedInt64Array = playtest.check_properties(size.x, size.y, game_mode, 1, SEED_COUNT)
+			if failed.size() > 0:
+				print('%dx%d 难度%d 不满足的种子：' % [size.x, size.y, game_mode], failed)
+			failed_count += failed.size()
+	print('%d个地图不满足' % failed_count)
+	quit(1 if failed_count > 0 else 0)
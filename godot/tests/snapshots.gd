@@ -0,0 +1,55 @@
+# 快照测试：按照固定的种子、大小和难度生成地图，执行点击脚本之后和tests/golden中的文件比较，
+# 有不同时退出码为1。生成器有意修改之后用BLESS=1重新生成golden文件。
+# cargo test不启动引擎生成同样的快照，也和这些文件比较
+# 用法：[BLESS=1] godot --headless --path godot -s tests/snapshots.gd
+extends SceneTree
+
+const GOLDEN_DIR := 'res://tests/golden/'
+
+func _init() -> void:
+	var bless := OS.get_environment('BLESS') == '1'
+	var playtest := Playtest.new()
+	var failed_count := 0
+	# 用例见rust/src/in_game/playtest.rs中的SNAPSHOT_CASES
+	var names: PackedStringArray = playtest.snapshot_cases()
+	for case_name in names:
+		var actual: String = playtest.snapshot_case(case_name)
+		if actual.is_empty():
+			print('%s：生成失败' % case_name)
+			failed_count += 1
+			continue
+		var path: String = GOLDEN_DIR + case_name + '.txt'
+		if bless:
+			var file := FileAccess.open(path, FileAccess.WRITE)
+			if file == null:
+				print('%s：无法写入%s' % [case_name, path])
+				failed_count += 1
+				continue
+			file.store_string(actual)
+			continue
+		if !FileAccess.file_exists(path):
+			print('%s：缺少%s，使用BLESS=1生成' % [case_name, path])
+			failed_count += 1
+			continue
+		var expected := FileAccess.get_file_as_string(path)
+		if expected != actual:
+			print('%s：和%s不同' % [case_name, path])
+			print_first_difference(expected, actual)
+			failed_count += 1
+	if bless:
+		print('已更新%d个golden文件' % names.size())
+	else:
+		print('%d/%d 通过' % [names.size() - failed_count, names.size()])
+	quit(1 if failed_count > 0 else 0)
+
+func print_first_difference(expected: String, actual: String) -> void:
+	var a := expected.split('\n')
+	var b := actual.split('\n')
+	for i in range(max(a.size(), b.size())):
+		var x: String = a[i] if i < a.size() else ''
+		var y: String = b[i] if i < b.size() else ''
+		if x != y:
+			print('  第%d行' % (i + 1))
+			print('  期望：', x)
+			print('  实际：', y)
+			return
@@ -0,0 +1,128 @@
+//! 联网对战的中继服务器，不需要Godot：把连接的客户端两两配对，在同一对客户端之间转发消息
+//!
+//! 用法：`cargo run --bin relay -- [端口]`，消息格式见`protocol::Message`
+use logic_islands::in_game::protocol::{Message, DEFAULT_PORT};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// 一场对战的两个客户端，下标为玩家编号
+#[derive(Default)]
+struct Room {
+    peers: [Option<(String, TcpStream)>; 2],
+}
+
+impl Room {
+    fn send(&mut self, player: usize, message: &Message) {
+        if let Some((_, stream)) = self.peers[player].as_mut() {
+            // 写入失败说明对方已经断开，由对方的线程处理
+            let _ = writeln!(stream, "{}", message.to_line());
+        }
+    }
+}
+
+/// 还在等待对手的房间
+type Lobby = Arc<Mutex<Option<Arc<Mutex<Room>>>>>;
+
+fn main() {
+    let port = std::env::args()
+        .nth(1)
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(DEFAULT_PORT);
+    let listener = match TcpListener::bind(("0.0.0.0", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("无法监听端口{}：{}", port, e);
+            std::process::exit(1);
+        }
+    };
+    println!("中继服务器监听端口{}", port);
+    let lobby: Lobby = Arc::default();
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let lobby = lobby.clone();
+        thread::spawn(move || {
+            let addr = stream
+                .peer_addr()
+                .map(|a| a.to_string())
+                .unwrap_or_default();
+            println!("{}已连接", addr);
+            handle_client(stream, lobby);
+            println!("{}已断开", addr);
+        });
+    }
+}
+
+fn handle_client(stream: TcpStream, lobby: Lobby) {
+    let Ok(writer) = stream.try_clone() else {
+        return;
+    };
+    let mut lines = BufReader::new(stream).lines();
+    // 第一条消息必须是join
+    let name = match lines.next().map(|l| l.map(|l| Message::from_line(&l))) {
+        Some(Ok(Ok(Message::Join { name }))) => name,
+        _ => return,
+    };
+    let (room, player) = join_room(&lobby, name, writer);
+    let opponent = 1 - player;
+    for line in lines {
+        let Ok(line) = line else {
+            break;
+        };
+        // from_line会检查地图参数和操作的范围，不合法的消息不会转发给对手
+        match Message::from_line(&line) {
+            // 只转发对战中的消息
+            Ok(
+                message @ (Message::Board { .. } | Message::Move { .. } | Message::Finished { .. }),
+            ) => {
+                room.lock().unwrap().send(opponent, &message);
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+    let mut guard = lobby.lock().unwrap();
+    if guard.as_ref().is_some_and(|r| Arc::ptr_eq(r, &room)) {
+        // 对手还没有加入
+        *guard = None;
+    }
+    drop(guard);
+    let mut room = room.lock().unwrap();
+    room.peers[player] = None;
+    room.send(opponent, &Message::Left);
+}
+
+/// 加入等待中的房间，没有时创建新的房间，返回房间和玩家编号
+fn join_room(lobby: &Lobby, name: String, writer: TcpStream) -> (Arc<Mutex<Room>>, usize) {
+    let mut guard = lobby.lock().unwrap();
+    let (room, player) = match guard.take() {
+        Some(room) => (room, 1),
+        None => {
+            let room = Arc::new(Mutex::new(Room::default()));
+            *guard = Some(room.clone());
+            (room, 0)
+        }
+    };
+    let mut r = room.lock().unwrap();
+    r.peers[player] = Some((name, writer));
+    r.send(
+        player,
+        &Message::Welcome {
+            player: player as i32,
+        },
+    );
+    if player == 1 {
+        // 互相通知对方的名字
+        for p in 0..2 {
+            if let Some((name, _)) = r.peers[1 - p].as_ref() {
+                let message = Message::Join { name: name.clone() };
+                r.send(p, &message);
+            }
+        }
+    }
+    drop(r);
+    (room, player)
+}
@@ -1,4 +1,5 @@
 mod camera_2d;
+pub mod settings;
 
 // use godot::prelude::*;
 
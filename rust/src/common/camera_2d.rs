@@ -1,6 +1,11 @@
+use super::settings::{Settings, SETTING_CHANGED};
 use godot::engine::global::MouseButton;
-use godot::engine::{InputEvent, InputEventMouse, InputEventMouseButton};
+use godot::engine::{
+    Input, InputEvent, InputEventMouse, InputEventMouseButton, InputEventScreenDrag,
+    InputEventScreenTouch,
+};
 use godot::prelude::*;
+use std::collections::HashMap;
 
 #[derive(GodotClass, Debug)]
 #[class(init, base = Camera2D)]
@@ -8,6 +13,9 @@ pub struct SimpleZoomCamera2D {
     //相机
     #[init(default = 1.0)]
     view_zoom: f32,
+    /// 平滑缩放的目标值
+    #[init(default = 1.0)]
+    target_zoom: f32,
     #[init(default = 0.1)]
     #[export]
     zoom_speed: f32,
@@ -17,34 +25,134 @@ pub struct SimpleZoomCamera2D {
     #[init(default = 0.5)]
     #[export]
     min_zoom: f32,
+    /// 缩放插值速度，0表示立即缩放
+    #[init(default = 12.0)]
+    #[export]
+    zoom_smoothing: f32,
+    /// 松开拖动后是否继续惯性平移
+    #[init(default = true)]
+    #[export]
+    kinetic_pan: bool,
+    /// 惯性平移的摩擦系数，越大停得越快
+    #[init(default = 5.0)]
+    #[export]
+    pan_friction: f32,
+    /// 键盘平移速度(屏幕像素/秒)，0表示禁用
+    #[init(default = 600.0)]
+    #[export]
+    keyboard_pan_speed: f32,
+    /// 鼠标靠近窗口边缘时平移
+    #[init(default = false)]
+    #[export]
+    edge_scroll_enabled: bool,
+    #[init(default = 20.0)]
+    #[export]
+    edge_scroll_margin: f32,
+    /// 边缘平移速度(屏幕像素/秒)
+    #[init(default = 600.0)]
+    #[export]
+    edge_scroll_speed: f32,
+    /// 是否限制相机范围，保证bounds至少有一部分在屏幕内
+    #[init(default = false)]
+    #[export]
+    bounds_enabled: bool,
+    #[export]
+    bounds: Rect2,
+    /// bounds至少保留在屏幕内的像素
+    #[init(default = 64.0)]
+    #[export]
+    bounds_min_visible: f32,
     #[init(default = false)]
     camera_drag: bool,
     camera_old_pos: Vector2,
+    /// 惯性平移速度(世界坐标/秒)
+    pan_velocity: Vector2,
     //鼠标
-    mouse_pos: Vector2,
+    zoom_anchor: Vector2,
     mouse_screen_pos: Vector2,
     mouse_screen_old_pos: Vector2,
+    //触摸
+    touch_points: HashMap<i32, Vector2>,
+    pinch_start_distance: f32,
+    pinch_start_zoom: f32,
+    pinch_start_center: Vector2,
     base: Base<Camera2D>,
 }
 
 #[godot_api]
 impl ICamera2D for SimpleZoomCamera2D {
-    fn process(&mut self, _delta: f64) {
+    fn process(&mut self, delta: f64) {
+        let delta = delta as f32;
         if self.camera_drag {
             let camera_old_pos = self.camera_old_pos;
             let mouse_screen_pos = self.mouse_screen_pos;
             let mouse_screen_old_pos = self.mouse_screen_old_pos;
             let view_zoom = self.view_zoom;
             let mut camera = self.base_mut();
-            camera.set_position(
-                camera_old_pos - (mouse_screen_pos - mouse_screen_old_pos) * (1.0 / view_zoom),
-            )
+            let old_pos = camera.get_position();
+            let new_pos =
+                camera_old_pos - (mouse_screen_pos - mouse_screen_old_pos) * (1.0 / view_zoom);
+            camera.set_position(new_pos);
+            drop(camera);
+            if delta > 0.0 {
+                // 记录拖动速度，松开后继续惯性平移
+                let velocity = (new_pos - old_pos) / delta;
+                self.pan_velocity = self.pan_velocity.lerp(velocity, 0.5);
+            }
+        } else if self.pan_velocity != Vector2::ZERO {
+            let velocity = self.pan_velocity;
+            self.pan_by(velocity * delta);
+            self.pan_velocity = self.pan_velocity * (-self.pan_friction * delta).exp();
+            if self.pan_velocity.length() < 1.0 {
+                self.pan_velocity = Vector2::ZERO;
+            }
         }
+        let screen_pan = self.keyboard_pan() + self.edge_scroll_pan();
+        if screen_pan != Vector2::ZERO {
+            let view_zoom = self.view_zoom;
+            self.pan_by(screen_pan * delta / view_zoom);
+        }
+        if self.view_zoom != self.target_zoom {
+            let view_zoom = if self.zoom_smoothing <= 0.0
+                || (self.view_zoom - self.target_zoom).abs() < 0.001
+            {
+                self.target_zoom
+            } else {
+                let weight = 1.0 - (-self.zoom_smoothing * delta).exp();
+                self.view_zoom + (self.target_zoom - self.view_zoom) * weight
+            };
+            self.zoom_to(view_zoom);
+        }
+        self.clamp_position();
     }
     fn unhandled_input(&mut self, event: Gd<InputEvent>) {
+        if event.is_class("InputEventScreenTouch".into()) {
+            let event = event.cast::<InputEventScreenTouch>();
+            if event.is_pressed() {
+                self.touch_points
+                    .insert(event.get_index(), event.get_position());
+            } else {
+                self.touch_points.remove(&event.get_index());
+            }
+            if self.touch_points.len() == 2 {
+                self.start_pinch();
+            }
+            return;
+        }
+        if event.is_class("InputEventScreenDrag".into()) {
+            let event = event.cast::<InputEventScreenDrag>();
+            if !self.touch_points.contains_key(&event.get_index()) {
+                return;
+            }
+            self.touch_points
+                .insert(event.get_index(), event.get_position());
+            if self.touch_points.len() == 2 {
+                self.update_pinch();
+            }
+            return;
+        }
         if event.is_class("InputEventMouse".into()) {
             let event = event.clone().cast::<InputEventMouse>();
-            self.mouse_pos = self.base().get_global_mouse_position();
             self.mouse_screen_pos = event.get_position();
         }
         if event.is_class("InputEventMouseButton".into()) {
@@ -75,11 +183,15 @@ impl ICamera2D for SimpleZoomCamera2D {
                 MouseButton::MIDDLE => {
                     if pressed {
                         self.camera_drag = true;
+                        self.pan_velocity = Vector2::ZERO;
                         self.mouse_screen_old_pos = self.mouse_screen_pos;
                         let camera = self.base();
                         self.camera_old_pos = camera.get_position();
                     } else {
                         self.camera_drag = false;
+                        if !self.kinetic_pan {
+                            self.pan_velocity = Vector2::ZERO;
+                        }
                     }
                 }
                 _ => {}
@@ -90,28 +202,196 @@ impl ICamera2D for SimpleZoomCamera2D {
 
 #[godot_api]
 impl SimpleZoomCamera2D {
+    /// 让rect完整显示在屏幕中间，四周留出padding像素，同时选择合适的缩放
+    #[func]
+    fn fit_rect(&mut self, rect: Rect2, padding: f32) {
+        let view_size = self.base().get_viewport_rect().size;
+        let content = rect.size + Vector2::new(padding, padding) * 2.0;
+        let view_zoom = if content.x <= 0.0 || content.y <= 0.0 {
+            1.0
+        } else {
+            (view_size.x / content.x).min(view_size.y / content.y)
+        };
+        self.view_zoom = view_zoom.clamp(self.min_zoom, self.max_zoom);
+        self.target_zoom = self.view_zoom;
+        self.pan_velocity = Vector2::ZERO;
+        let view_zoom = self.view_zoom;
+        let mut camera = self.base_mut();
+        camera.set_zoom(Vector2::new(view_zoom, view_zoom));
+        camera.set_position(rect.center());
+        camera.force_update_scroll();
+    }
+    /// 使用设置中的缩放速度，并在设置修改时更新
+    #[func]
+    fn use_settings(&mut self, mut settings: Gd<Settings>) {
+        let value = settings.bind().get_setting("zoom_speed".into());
+        self.on_setting_changed("zoom_speed".into(), value);
+        let this = self.base().clone();
+        settings.connect(
+            SETTING_CHANGED.into(),
+            Callable::from_object_method(&this, "on_setting_changed"),
+        );
+    }
+    #[func]
+    fn on_setting_changed(&mut self, key: GString, value: Variant) {
+        if key.to_string() == "zoom_speed" {
+            self.zoom_speed = value.try_to::<f64>().unwrap_or(0.1) as f32;
+        }
+    }
+    /// 设置并启用相机范围
+    #[func]
+    fn limit_to_rect(&mut self, rect: Rect2) {
+        self.bounds = rect;
+        self.bounds_enabled = true;
+        self.clamp_position();
+    }
+    /// 把相机限制在bounds内，平移和缩放之后调用
+    fn clamp_position(&mut self) {
+        if !self.bounds_enabled {
+            return;
+        }
+        let bounds = self.bounds;
+        let half_view = self.base().get_viewport_rect().size / (2.0 * self.view_zoom);
+        let min_visible = self.bounds_min_visible / self.view_zoom;
+        let visible = Vector2::new(
+            min_visible.min(bounds.size.x),
+            min_visible.min(bounds.size.y),
+        );
+        let min = bounds.position + visible - half_view;
+        let max = bounds.end() - visible + half_view;
+        let mut camera = self.base_mut();
+        let p = camera.get_position();
+        camera.set_position(Vector2::new(
+            clamp_or_center(p.x, min.x, max.x),
+            clamp_or_center(p.y, min.y, max.y),
+        ));
+    }
+    /// 两指的中心和距离
+    fn pinch_state(&self) -> (Vector2, f32) {
+        let points: Vec<Vector2> = self.touch_points.values().copied().collect();
+        let center = (points[0] + points[1]) / 2.0;
+        (center, points[0].distance_to(points[1]))
+    }
+    fn start_pinch(&mut self) {
+        let (center, distance) = self.pinch_state();
+        self.pinch_start_center = center;
+        self.pinch_start_distance = distance.max(1.0);
+        self.pinch_start_zoom = self.view_zoom;
+        self.camera_drag = false;
+        self.pan_velocity = Vector2::ZERO;
+        self.camera_old_pos = self.base().get_position();
+    }
+    /// 双指平移和缩放，保持手势开始时两指中心下的世界坐标不变
+    fn update_pinch(&mut self) {
+        let (center, distance) = self.pinch_state();
+        let view_zoom = (self.pinch_start_zoom * distance / self.pinch_start_distance)
+            .clamp(self.min_zoom, self.max_zoom);
+        self.view_zoom = view_zoom;
+        self.target_zoom = view_zoom;
+        let start_zoom = self.pinch_start_zoom;
+        let start_center = self.pinch_start_center;
+        let camera_old_pos = self.camera_old_pos;
+        let mut camera = self.base_mut();
+        let view_center = camera.get_viewport_rect().size / 2.0;
+        let anchor = camera_old_pos + (start_center - view_center) / start_zoom;
+        camera.set_zoom(Vector2::new(view_zoom, view_zoom));
+        camera.set_position(anchor - (center - view_center) / view_zoom);
+        camera.force_update_scroll();
+        drop(camera);
+        self.clamp_position();
+    }
     fn zoom_sub(&mut self) {
-        if self.view_zoom <= self.min_zoom {
+        if self.target_zoom <= self.min_zoom {
             return;
         }
-        self.view_zoom -= self.get_zoom_speed().abs();
-        self.zoom()
+        self.target_zoom = (self.target_zoom - self.get_zoom_speed().abs()).max(self.min_zoom);
+        self.zoom_anchor = self.mouse_screen_pos;
     }
     fn zoom_plus(&mut self) {
-        if self.view_zoom >= self.max_zoom {
+        if self.target_zoom >= self.max_zoom {
             return;
         }
-        self.view_zoom += self.get_zoom_speed().abs();
-        self.zoom()
+        self.target_zoom = (self.target_zoom + self.get_zoom_speed().abs()).min(self.max_zoom);
+        self.zoom_anchor = self.mouse_screen_pos;
     }
-    fn zoom(&mut self) {
-        let target_zoom = Vector2::new(self.view_zoom, self.view_zoom);
-        let mouse_pos = self.mouse_pos;
+    /// 缩放到view_zoom，保持zoom_anchor(屏幕坐标)下的世界坐标不变
+    fn zoom_to(&mut self, view_zoom: f32) {
+        let old_zoom = self.view_zoom;
+        let zoom_anchor = self.zoom_anchor;
+        self.view_zoom = view_zoom;
         let mut camera = self.base_mut();
-        camera.set_zoom(target_zoom);
-        let p = -(camera.get_global_mouse_position() - mouse_pos);
-        let p = camera.get_position() + p;
-        camera.set_position(p);
+        let view_center = camera.get_viewport_rect().size / 2.0;
+        let anchor = camera.get_position() + (zoom_anchor - view_center) / old_zoom;
+        camera.set_zoom(Vector2::new(view_zoom, view_zoom));
+        camera.set_position(anchor - (zoom_anchor - view_center) / view_zoom);
         camera.force_update_scroll();
     }
+    fn pan_by(&mut self, offset: Vector2) {
+        let mut camera = self.base_mut();
+        let p = camera.get_position() + offset;
+        camera.set_position(p);
+    }
+    /// 方向键平移(屏幕像素/秒)
+    fn keyboard_pan(&self) -> Vector2 {
+        if self.keyboard_pan_speed <= 0.0 {
+            return Vector2::ZERO;
+        }
+        Input::singleton().get_vector(
+            "ui_left".into(),
+            "ui_right".into(),
+            "ui_up".into(),
+            "ui_down".into(),
+        ) * self.keyboard_pan_speed
+    }
+    /// 鼠标在窗口边缘时的平移(屏幕像素/秒)
+    fn edge_scroll_pan(&self) -> Vector2 {
+        if !self.edge_scroll_enabled || self.camera_drag {
+            return Vector2::ZERO;
+        }
+        let view_size = self.base().get_viewport_rect().size;
+        let mouse = self.mouse_screen_pos;
+        let margin = self.edge_scroll_margin;
+        if mouse.x < 0.0 || mouse.y < 0.0 || mouse.x > view_size.x || mouse.y > view_size.y {
+            return Vector2::ZERO;
+        }
+        let mut dir = Vector2::ZERO;
+        if mouse.x < margin {
+            dir.x = -1.0;
+        } else if mouse.x > view_size.x - margin {
+            dir.x = 1.0;
+        }
+        if mouse.y < margin {
+            dir.y = -1.0;
+        } else if mouse.y > view_size.y - margin {
+            dir.y = 1.0;
+        }
+        dir * self.edge_scroll_speed
+    }
+}
+
+/// 限制在min和max之间。视野比边界大时min大于max，这时取中间，让边界在视野中居中
+fn clamp_or_center(value: f32, min: f32, max: f32) -> f32 {
+    if min <= max {
+        value.clamp(min, max)
+    } else if min > max {
+        (min + max) / 2.0
+    } else {
+        // 有NaN时保持不变
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamp_or_center() {
+        assert_eq!(clamp_or_center(5.0, 0.0, 10.0), 5.0);
+        assert_eq!(clamp_or_center(-5.0, 0.0, 10.0), 0.0);
+        assert_eq!(clamp_or_center(15.0, 0.0, 10.0), 10.0);
+        // 视野比边界大
+        assert_eq!(clamp_or_center(15.0, 10.0, 0.0), 5.0);
+        assert_eq!(clamp_or_center(15.0, f32::NAN, 0.0), 15.0);
+    }
 }
@@ -0,0 +1,463 @@
+use godot::engine::global::Error;
+use godot::engine::{ConfigFile, Resource};
+use godot::prelude::*;
+use std::collections::HashMap;
+
+/// 设置文件
+const SETTINGS_PATH: &'static str = "user://settings.cfg";
+/// 当前的设置文件版本，修改存储格式时加一并在`MIGRATIONS`中添加转换。
+/// 版本1是第一个保存到文件的版本，之前的设置只在`Global.Cfg`中，重启之后就丢失了
+const VERSION: i32 = 1;
+const META_SECTION: &'static str = "meta";
+const VERSION_KEY: &'static str = "version";
+pub const SETTING_CHANGED: &'static str = "setting_changed";
+
+/// 一项设置的值
+#[derive(Debug, Clone, PartialEq)]
+enum SettingValue {
+    Int(i32),
+    Float(f64),
+    Bool(bool),
+    Text(String),
+    Size(Vector2i),
+}
+
+/// 设置的类型、默认值和合法范围
+#[derive(Debug, Clone, Copy)]
+enum SettingKind {
+    Int {
+        default: i32,
+        min: i32,
+        max: i32,
+    },
+    Float {
+        default: f64,
+        min: f64,
+        max: f64,
+    },
+    Bool {
+        default: bool,
+    },
+    /// 只能是options中的一个
+    Choice {
+        default: &'static str,
+        options: &'static [&'static str],
+    },
+    /// 只能包含字母、数字、`_`和`-`，可以用作文件名
+    Name {
+        default: &'static str,
+    },
+    /// 宽和高的范围相同
+    Size {
+        default: (i32, i32),
+        min: i32,
+        max: i32,
+    },
+}
+
+#[derive(Debug)]
+struct SettingSpec {
+    section: &'static str,
+    key: &'static str,
+    kind: SettingKind,
+}
+
+const SPECS: [SettingSpec; 11] = [
+    SettingSpec {
+        section: "map",
+        key: "map_size",
+        kind: SettingKind::Size {
+            default: (10, 7),
+            min: 5,
+            max: 50,
+        },
+    },
+    SettingSpec {
+        section: "map",
+        key: "game_mode",
+        kind: SettingKind::Int {
+            default: 1,
+            min: 1,
+            max: 20,
+        },
+    },
+    SettingSpec {
+        section: "map",
+        key: "max_bridges_per_link",
+        kind: SettingKind::Int {
+            default: 2,
+            min: 1,
+            max: 3,
+        },
+    },
+    SettingSpec {
+        section: "map",
+        key: "map_variant",
+        kind: SettingKind::Choice {
+            default: "Square",
+            options: &["Square", "Hex", "Diagonal"],
+        },
+    },
+    SettingSpec {
+        section: "map",
+        key: "map_wrap",
+        kind: SettingKind::Bool { default: false },
+    },
+    SettingSpec {
+        section: "map",
+        key: "color_count",
+        kind: SettingKind::Int {
+            default: 1,
+            min: 1,
+            max: 5,
+        },
+    },
+    SettingSpec {
+        section: "map",
+        key: "hidden_clue_ratio",
+        kind: SettingKind::Float {
+            default: 0.0,
+            min: 0.0,
+            max: 0.5,
+        },
+    },
+    SettingSpec {
+        section: "game",
+        key: "time_limit",
+        kind: SettingKind::Float {
+            default: 0.0,
+            min: 0.0,
+            max: 3600.0,
+        },
+    },
+    SettingSpec {
+        section: "view",
+        key: "zoom_speed",
+        kind: SettingKind::Float {
+            default: -0.1,
+            min: -1.0,
+            max: 1.0,
+        },
+    },
+    SettingSpec {
+        section: "view",
+        key: "map_item_scale",
+        kind: SettingKind::Float {
+            default: 30.0,
+            min: 10.0,
+            max: 100.0,
+        },
+    },
+    SettingSpec {
+        section: "player",
+        key: "profile",
+        kind: SettingKind::Name { default: "default" },
+    },
+];
+
+impl SettingKind {
+    fn default_value(self) -> SettingValue {
+        match self {
+            SettingKind::Int { default, .. } => SettingValue::Int(default),
+            SettingKind::Float { default, .. } => SettingValue::Float(default),
+            SettingKind::Bool { default } => SettingValue::Bool(default),
+            SettingKind::Choice { default, .. } | SettingKind::Name { default } => {
+                SettingValue::Text(default.to_string())
+            }
+            SettingKind::Size { default, .. } => {
+                SettingValue::Size(Vector2i::new(default.0, default.1))
+            }
+        }
+    }
+    /// 把值限制在合法范围内，类型不对时使用默认值
+    fn validate(self, value: SettingValue) -> SettingValue {
+        match (self, value) {
+            (SettingKind::Int { min, max, .. }, SettingValue::Int(v)) => {
+                SettingValue::Int(v.clamp(min, max))
+            }
+            // GDScript和JSON中的数字可能是浮点数
+            (SettingKind::Int { min, max, .. }, SettingValue::Float(v)) if v.is_finite() => {
+                SettingValue::Int((v.round() as i32).clamp(min, max))
+            }
+            (SettingKind::Float { min, max, .. }, SettingValue::Float(v)) if !v.is_nan() => {
+                SettingValue::Float(v.clamp(min, max))
+            }
+            (SettingKind::Float { min, max, .. }, SettingValue::Int(v)) => {
+                SettingValue::Float((v as f64).clamp(min, max))
+            }
+            (SettingKind::Bool { .. }, SettingValue::Bool(v)) => SettingValue::Bool(v),
+            (SettingKind::Choice { options, .. }, SettingValue::Text(v))
+                if options.contains(&v.as_str()) =>
+            {
+                SettingValue::Text(v)
+            }
+            (SettingKind::Name { .. }, SettingValue::Text(v))
+                if !v.is_empty()
+                    && v.chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') =>
+            {
+                SettingValue::Text(v)
+            }
+            (SettingKind::Size { min, max, .. }, SettingValue::Size(v)) => {
+                SettingValue::Size(Vector2i::new(v.x.clamp(min, max), v.y.clamp(min, max)))
+            }
+            (kind, _) => kind.default_value(),
+        }
+    }
+}
+
+impl SettingValue {
+    fn to_variant(&self) -> Variant {
+        match self {
+            SettingValue::Int(v) => v.to_variant(),
+            SettingValue::Float(v) => v.to_variant(),
+            SettingValue::Bool(v) => v.to_variant(),
+            SettingValue::Text(v) => GString::from(v.as_str()).to_variant(),
+            SettingValue::Size(v) => v.to_variant(),
+        }
+    }
+    fn from_variant(value: &Variant) -> Option<Self> {
+        match value.get_type() {
+            VariantType::Int => Some(SettingValue::Int(value.to::<i64>() as i32)),
+            VariantType::Float => Some(SettingValue::Float(value.to::<f64>())),
+            VariantType::Bool => Some(SettingValue::Bool(value.to::<bool>())),
+            VariantType::String | VariantType::StringName => {
+                Some(SettingValue::Text(value.to_string()))
+            }
+            VariantType::Vector2i => Some(SettingValue::Size(value.to::<Vector2i>())),
+            _ => None,
+        }
+    }
+}
+
+fn spec_index(key: &str) -> Option<usize> {
+    SPECS.iter().position(|s| s.key == key)
+}
+
+/// 整数设置的合法范围，地图大小为宽和高的范围，用于检查不是来自设置的值，例如对手发来的地图参数
+pub fn int_range(key: &str) -> Option<(i32, i32)> {
+    match SPECS[spec_index(key)?].kind {
+        SettingKind::Int { min, max, .. } | SettingKind::Size { min, max, .. } => Some((min, max)),
+        _ => None,
+    }
+}
+
+type Entries = HashMap<String, SettingValue>;
+
+/// 版本之间的转换，第i项把版本i+1的设置转换为版本i+2，长度为`VERSION - 1`
+const MIGRATIONS: &[fn(Entries) -> Entries] = &[];
+const _: () = assert!(MIGRATIONS.len() as i32 == VERSION - 1);
+
+/// 把旧版本的设置依次转换为当前版本，entries的key为`section/key`，
+/// 更新的版本保持不变，不认识的设置在读取时被忽略
+fn migrate(entries: Entries, version: i32) -> Entries {
+    MIGRATIONS
+        .iter()
+        .skip((version.max(1) - 1) as usize)
+        .fold(entries, |entries, migration| migration(entries))
+}
+
+/// 从存储的设置中读出每一项的值，缺少或者不合法时使用默认值
+fn values_from_entries(entries: &HashMap<String, SettingValue>) -> Vec<SettingValue> {
+    SPECS
+        .iter()
+        .map(
+            |spec| match entries.get(&format!("{}/{}", spec.section, spec.key)) {
+                Some(v) => spec.kind.validate(v.clone()),
+                None => spec.kind.default_value(),
+            },
+        )
+        .collect()
+}
+
+/// 游戏设置，保存在`user://`中，修改时发出`setting_changed`信号
+#[derive(GodotClass, Debug)]
+#[class(init, base = Resource)]
+pub struct Settings {
+    /// 和`SPECS`一一对应
+    #[init(default = SPECS.iter().map(|s| s.kind.default_value()).collect())]
+    values: Vec<SettingValue>,
+    base: Base<Resource>,
+}
+
+#[godot_api]
+impl Settings {
+    /// 设置被修改，value为修正后的值
+    #[signal]
+    pub fn setting_changed(key: GString, value: Variant) {}
+    /// 读取保存的设置，文件不存在时为默认设置
+    #[func]
+    fn load_saved() -> Gd<Settings> {
+        let mut settings = Settings::new_gd();
+        settings.bind_mut().load_from(SETTINGS_PATH.into());
+        settings
+    }
+    #[func]
+    pub fn get_setting(&self, key: GString) -> Variant {
+        match spec_index(&key.to_string()) {
+            Some(i) => self.values[i].to_variant(),
+            None => {
+                godot_error!("未知的设置：{}", key);
+                Variant::nil()
+            }
+        }
+    }
+    /// 修改设置，超出范围的值会被修正，返回修正后的值
+    #[func]
+    fn set_setting(&mut self, key: GString, value: Variant) -> Variant {
+        let Some(i) = spec_index(&key.to_string()) else {
+            godot_error!("未知的设置：{}", key);
+            return Variant::nil();
+        };
+        let kind = SPECS[i].kind;
+        let value = match SettingValue::from_variant(&value) {
+            Some(v) => kind.validate(v),
+            None => kind.default_value(),
+        };
+        self.update(i, value);
+        self.values[i].to_variant()
+    }
+    /// 所有设置的名字
+    #[func]
+    pub fn keys(&self) -> PackedStringArray {
+        let mut keys = PackedStringArray::new();
+        for spec in SPECS.iter() {
+            keys.push(spec.key.into());
+        }
+        keys
+    }
+    #[func]
+    fn reset_to_defaults(&mut self) {
+        for i in 0..SPECS.len() {
+            self.update(i, SPECS[i].kind.default_value());
+        }
+    }
+    /// 从文件读取设置，旧版本的文件会被转换，文件不存在时返回false并保持当前设置
+    #[func]
+    fn load_from(&mut self, path: GString) -> bool {
+        let mut config = ConfigFile::new_gd();
+        if config.load(path) != Error::OK {
+            return false;
+        }
+        // 保存时总是记录版本号，缺少时视为版本1
+        let version = if config.has_section_key(META_SECTION.into(), VERSION_KEY.into()) {
+            config
+                .get_value(META_SECTION.into(), VERSION_KEY.into())
+                .try_to::<i32>()
+                .unwrap_or(1)
+        } else {
+            1
+        };
+        let mut entries = HashMap::new();
+        for section in config.get_sections().as_slice() {
+            for key in config.get_section_keys(section.clone()).as_slice() {
+                let value = config.get_value(section.clone(), key.clone());
+                if let Some(v) = SettingValue::from_variant(&value) {
+                    entries.insert(format!("{}/{}", section, key), v);
+                }
+            }
+        }
+        let values = values_from_entries(&migrate(entries, version));
+        for (i, value) in values.into_iter().enumerate() {
+            self.update(i, value);
+        }
+        true
+    }
+    #[func]
+    fn save(&self) -> bool {
+        self.save_to(SETTINGS_PATH.into())
+    }
+    #[func]
+    fn save_to(&self, path: GString) -> bool {
+        let mut config = ConfigFile::new_gd();
+        config.set_value(
+            META_SECTION.into(),
+            VERSION_KEY.into(),
+            Variant::from(VERSION),
+        );
+        for (spec, value) in SPECS.iter().zip(self.values.iter()) {
+            config.set_value(spec.section.into(), spec.key.into(), value.to_variant());
+        }
+        config.save(path) == Error::OK
+    }
+    /// 修改第i项设置，值改变时发出信号
+    fn update(&mut self, i: usize, value: SettingValue) {
+        if self.values[i] == value {
+            return;
+        }
+        self.values[i] = value;
+        let args = [
+            GString::from(SPECS[i].key).to_variant(),
+            self.values[i].to_variant(),
+        ];
+        self.base_mut().emit_signal(SETTING_CHANGED.into(), &args);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let game_mode = SPECS[spec_index("game_mode").unwrap()].kind;
+        assert_eq!(
+            game_mode.validate(SettingValue::Int(0)),
+            SettingValue::Int(1)
+        );
+        assert_eq!(
+            game_mode.validate(SettingValue::Int(99)),
+            SettingValue::Int(20)
+        );
+        assert_eq!(
+            game_mode.validate(SettingValue::Float(3.0)),
+            SettingValue::Int(3)
+        );
+        assert_eq!(
+            game_mode.validate(SettingValue::Text("3".into())),
+            SettingValue::Int(1)
+        );
+        let variant = SPECS[spec_index("map_variant").unwrap()].kind;
+        assert_eq!(
+            variant.validate(SettingValue::Text("Hex".into())),
+            SettingValue::Text("Hex".into())
+        );
+        assert_eq!(
+            variant.validate(SettingValue::Text("Round".into())),
+            SettingValue::Text("Square".into())
+        );
+        let profile = SPECS[spec_index("profile").unwrap()].kind;
+        assert_eq!(
+            profile.validate(SettingValue::Text("../x".into())),
+            SettingValue::Text("default".into())
+        );
+        let map_size = SPECS[spec_index("map_size").unwrap()].kind;
+        assert_eq!(
+            map_size.validate(SettingValue::Size(Vector2i::new(3, 60))),
+            SettingValue::Size(Vector2i::new(5, 50))
+        );
+    }
+    #[test]
+    fn test_migrate() {
+        let entries: Entries = [
+            ("map/game_mode", SettingValue::Int(3)),
+            ("view/zoom_speed", SettingValue::Float(0.2)),
+            ("map/unknown", SettingValue::Bool(true)),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+        // 当前版本和更新的版本不会转换
+        assert_eq!(migrate(entries.clone(), VERSION), entries);
+        assert_eq!(migrate(entries.clone(), VERSION + 1), entries);
+        let values = values_from_entries(&migrate(entries, VERSION));
+        assert_eq!(
+            values[spec_index("game_mode").unwrap()],
+            SettingValue::Int(3)
+        );
+        assert_eq!(
+            values[spec_index("zoom_speed").unwrap()],
+            SettingValue::Float(0.2)
+        );
+    }
+}
@@ -1,6 +1,20 @@
+mod board;
 mod bridge;
+mod campaign;
+mod daily;
 mod game_map;
+mod game_session;
+mod generator;
+mod invariants;
 mod island;
+mod net_client;
+mod playtest;
+pub mod protocol;
+mod puzzle;
+mod replay;
+mod shape;
+mod solver;
+mod stats;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,349 @@
+use super::game_map::{BridgeAction, Direction2D, MapVariant};
+use super::puzzle::Puzzle;
+use super::shape::Shape;
+use godot::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+/// 岛屿的状态
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IslandState {
+    /// 需要的桥梁数量
+    pub count: i32,
+    pub color_id: i32,
+    /// 隐藏了需要的桥梁数量
+    pub hidden: bool,
+    /// 当前的桥梁数量
+    pub current: i32,
+}
+
+/// 玩家搭桥的规则。`GameMap`从岛屿节点读取状态，`Board`不需要引擎，
+/// 两者使用相同的规则，快照测试可以不启动引擎得到和游戏中相同的结果
+pub trait BridgeRules {
+    fn shape(&self) -> Shape<'_>;
+    fn islands_pos(&self) -> &[Vector2i];
+    /// 不是岛屿时为None
+    fn island(&self, pos: Vector2i) -> Option<IslandState>;
+    /// 岛屿在direction方向上的桥梁数量
+    fn bridge_count(&self, pos: Vector2i, direction: Direction2D) -> i32;
+    fn is_obstacle(&self, pos: Vector2i) -> bool;
+    /// 玩家的桥梁经过的格子和对角线交点
+    fn user_bridges(&self) -> (&HashSet<Vector2i>, &HashSet<Vector2i>);
+    fn user_bridges_mut(&mut self) -> (&mut HashSet<Vector2i>, &mut HashSet<Vector2i>);
+    fn max_bridges_per_link(&self) -> i32;
+    /// 编辑器模式：搭桥不受岛屿数量限制
+    fn is_editor(&self) -> bool;
+
+    /// 沿方向查找可以搭桥的目标岛屿
+    fn find_link_target(&self, src_pos: Vector2i, direction: Direction2D) -> Option<Vector2i> {
+        // 坐标可能来自录像、对手或者脚本，不一定是岛屿
+        let src = self.island(src_pos)?;
+        let shape = self.shape();
+        if !shape.variant.directions().contains(&direction) {
+            return None;
+        }
+        let src_has_bridge = self.bridge_count(src_pos, direction);
+        let (user_points, user_crossings) = self.user_bridges();
+        let mut target_pos = src_pos;
+        loop {
+            let crossing = shape.crossing_key(target_pos, direction);
+            target_pos = shape.next_pos(target_pos, direction)?;
+            if target_pos == src_pos || self.is_obstacle(target_pos) {
+                // 环形地图绕了一圈，或者被障碍物挡住
+                return None;
+            }
+            if (user_points.contains(&target_pos) || user_crossings.contains(&crossing))
+                && src_has_bridge == 0
+            {
+                return None;
+            }
+            if let Some(target) = self.island(target_pos) {
+                // 不同颜色的岛屿之间不能搭桥
+                if target.color_id != src.color_id {
+                    return None;
+                }
+                return Some(target_pos);
+            }
+        }
+    }
+    /// 两个岛屿是否都还能再搭n座桥，隐藏数量的岛屿和编辑器模式没有限制
+    fn can_add_bridge(&self, src_pos: Vector2i, target_pos: Vector2i, n: i32) -> bool {
+        if self.is_editor() {
+            return true;
+        }
+        [src_pos, target_pos].iter().all(|p| match self.island(*p) {
+            Some(island) => island.hidden || island.current + n <= island.count,
+            None => false,
+        })
+    }
+    /// 点击桥梁之后的目标岛屿和桥梁数量：增加一座桥，达到上限之后拆掉所有桥，
+    /// 无事发生时为None
+    fn gen_bridge_count(
+        &self,
+        src_pos: Vector2i,
+        direction: Direction2D,
+    ) -> Option<(Vector2i, i32)> {
+        // 已经被别的岛屿搭桥，或者不在地图范围内时为None
+        let target_pos = self.find_link_target(src_pos, direction)?;
+        let src_has_bridge = self.bridge_count(src_pos, direction);
+        if src_has_bridge >= self.max_bridges_per_link()
+            || !self.can_add_bridge(src_pos, target_pos, 1)
+        {
+            if src_has_bridge == 0 {
+                return None;
+            }
+            // 拆桥
+            return Some((target_pos, 0));
+        }
+        // 搭桥
+        Some((target_pos, src_has_bridge + 1))
+    }
+    /// 桥梁数量从before变为count之后，更新玩家的桥梁经过的格子和交点
+    fn mark_user_bridge(
+        &mut self,
+        src_pos: Vector2i,
+        target_pos: Vector2i,
+        direction: Direction2D,
+        before: i32,
+        count: i32,
+    ) {
+        let (points, crossings) = self.shape().walk_link(src_pos, target_pos, direction);
+        let (user_points, user_crossings) = self.user_bridges_mut();
+        if count == 0 {
+            for p in points.iter() {
+                user_points.remove(p);
+            }
+            for c in crossings.iter() {
+                user_crossings.remove(c);
+            }
+        } else if before == 0 {
+            user_points.extend(points);
+            user_crossings.extend(crossings);
+        }
+    }
+    /// 当前所有的桥梁，每条连线从两端各出现一次
+    fn current_bridges(&self) -> Vec<(Vector2i, Direction2D, i32)> {
+        let mut res = vec![];
+        for pos in self.islands_pos().iter() {
+            for direction in self.shape().variant.directions() {
+                let count = self.bridge_count(*pos, *direction);
+                if count > 0 {
+                    res.push((*pos, *direction, count));
+                }
+            }
+        }
+        res
+    }
+    /// 是否已经完成：所有岛屿的桥梁数量都满足要求(隐藏数量的岛屿除外)，
+    /// 并且每种颜色的岛屿连成一个网络
+    fn is_solved(&self) -> bool {
+        if self.islands_pos().is_empty() {
+            return false;
+        }
+        let all_full = self.islands_pos().iter().all(|p| {
+            self.island(*p)
+                .is_some_and(|island| island.hidden || island.current == island.count)
+        });
+        if !all_full {
+            return false;
+        }
+        let mut visited = HashSet::new();
+        let mut networks = HashSet::new();
+        for p in self.islands_pos().iter() {
+            if visited.contains(p) {
+                continue;
+            }
+            let color_id = self.island(*p).map_or(0, |island| island.color_id);
+            if !networks.insert(color_id) {
+                // 同一种颜色有两个网络
+                return false;
+            }
+            visited.insert(*p);
+            let mut stack = vec![*p];
+            while let Some(current) = stack.pop() {
+                for direction in self.shape().variant.directions().iter() {
+                    if self.bridge_count(current, *direction) == 0 {
+                        continue;
+                    }
+                    if let Some(next) = self.find_link_target(current, *direction) {
+                        if visited.insert(next) {
+                            stack.push(next);
+                        }
+                    }
+                }
+            }
+        }
+        true
+    }
+}
+
+/// 不需要引擎的棋盘，从谜题开始按照`BridgeRules`搭桥，用于快照测试
+#[derive(Debug)]
+pub struct Board {
+    width: i32,
+    height: i32,
+    variant: MapVariant,
+    wrap: bool,
+    mask: Option<HashSet<Vector2i>>,
+    max_bridges_per_link: i32,
+    obstacles: HashSet<Vector2i>,
+    islands_pos: Vec<Vector2i>,
+    islands: HashMap<Vector2i, IslandState>,
+    /// 每个岛屿每个方向上的桥梁数量，没有桥时不记录
+    bridges: HashMap<(Vector2i, Direction2D), i32>,
+    user_bridge_points: HashSet<Vector2i>,
+    user_bridge_crossings: HashSet<Vector2i>,
+}
+
+impl Board {
+    pub fn from_puzzle(puzzle: &Puzzle) -> Self {
+        let mask = if puzzle.holes.is_empty() {
+            None
+        } else {
+            let holes: HashSet<Vector2i> = puzzle.holes.iter().copied().collect();
+            Some(
+                (0..puzzle.height)
+                    .flat_map(|y| (0..puzzle.width).map(move |x| Vector2i::new(x, y)))
+                    .filter(|p| !holes.contains(p))
+                    .collect(),
+            )
+        };
+        Board {
+            width: puzzle.width,
+            height: puzzle.height,
+            variant: puzzle.variant,
+            wrap: puzzle.wrap,
+            mask,
+            max_bridges_per_link: puzzle.max_bridges_per_link,
+            obstacles: puzzle.obstacles.iter().copied().collect(),
+            islands_pos: puzzle.islands.iter().map(|i| i.pos).collect(),
+            islands: puzzle
+                .islands
+                .iter()
+                .map(|i| {
+                    let state = IslandState {
+                        count: i.count,
+                        color_id: i.color_id,
+                        hidden: i.hidden,
+                        current: 0,
+                    };
+                    (i.pos, state)
+                })
+                .collect(),
+            bridges: HashMap::new(),
+            user_bridge_points: HashSet::new(),
+            user_bridge_crossings: HashSet::new(),
+        }
+    }
+    /// 和`GameMap::user_gen_bridge`相同的点击
+    pub fn click(&mut self, src_pos: Vector2i, direction: Direction2D) -> BridgeAction {
+        let Some((target_pos, count)) = self.gen_bridge_count(src_pos, direction) else {
+            return BridgeAction::Pass;
+        };
+        let before = self.bridge_count(src_pos, direction);
+        if before == count {
+            return BridgeAction::Pass;
+        }
+        for (pos, d) in [(src_pos, direction), (target_pos, direction.opposite())] {
+            if count == 0 {
+                self.bridges.remove(&(pos, d));
+            } else {
+                self.bridges.insert((pos, d), count);
+            }
+            self.islands.get_mut(&pos).unwrap().current += count - before;
+        }
+        self.mark_user_bridge(src_pos, target_pos, direction, before, count);
+        BridgeAction::from_count(count)
+    }
+}
+
+impl BridgeRules for Board {
+    fn shape(&self) -> Shape<'_> {
+        Shape {
+            width: self.width,
+            height: self.height,
+            variant: self.variant,
+            wrap: self.wrap,
+            mask: self.mask.as_ref(),
+        }
+    }
+    fn islands_pos(&self) -> &[Vector2i] {
+        &self.islands_pos
+    }
+    fn island(&self, pos: Vector2i) -> Option<IslandState> {
+        self.islands.get(&pos).copied()
+    }
+    fn bridge_count(&self, pos: Vector2i, direction: Direction2D) -> i32 {
+        self.bridges.get(&(pos, direction)).copied().unwrap_or(0)
+    }
+    fn is_obstacle(&self, pos: Vector2i) -> bool {
+        self.obstacles.contains(&pos)
+    }
+    fn user_bridges(&self) -> (&HashSet<Vector2i>, &HashSet<Vector2i>) {
+        (&self.user_bridge_points, &self.user_bridge_crossings)
+    }
+    fn user_bridges_mut(&mut self) -> (&mut HashSet<Vector2i>, &mut HashSet<Vector2i>) {
+        (
+            &mut self.user_bridge_points,
+            &mut self.user_bridge_crossings,
+        )
+    }
+    fn max_bridges_per_link(&self) -> i32 {
+        self.max_bridges_per_link
+    }
+    fn is_editor(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_click() {
+        let puzzle =
+            Puzzle::from_text("size 5 3\ngrid\n3 . 2 . .\n. . . . .\n1 . . . .\n").unwrap();
+        let mut board = Board::from_puzzle(&puzzle);
+        let origin = Vector2i::new(0, 0);
+        assert_eq!(
+            board.click(origin, Direction2D::Right),
+            BridgeAction::Single
+        );
+        assert_eq!(
+            board.click(origin, Direction2D::Right),
+            BridgeAction::Double
+        );
+        // 右边的岛屿已经满了，再点击拆桥
+        assert_eq!(
+            board.click(origin, Direction2D::Right),
+            BridgeAction::Remove
+        );
+        assert_eq!(board.island(origin).unwrap().current, 0);
+        // 不是岛屿，或者这个方向没有岛屿
+        assert_eq!(
+            board.click(Vector2i::new(1, 1), Direction2D::Right),
+            BridgeAction::Pass
+        );
+        assert_eq!(board.click(origin, Direction2D::Left), BridgeAction::Pass);
+        board.click(origin, Direction2D::Right);
+        board.click(origin, Direction2D::Right);
+        assert!(!board.is_solved());
+        board.click(origin, Direction2D::Down);
+        assert!(board.is_solved());
+    }
+    #[test]
+    fn test_user_bridge_blocks() {
+        let puzzle = Puzzle::from_text("size 3 3\ngrid\n. 1 .\n1 . 1\n. 1 .\n").unwrap();
+        let mut board = Board::from_puzzle(&puzzle);
+        assert_eq!(
+            board.click(Vector2i::new(0, 1), Direction2D::Right),
+            BridgeAction::Single
+        );
+        // 竖着的桥和已有的桥交叉
+        assert_eq!(
+            board.click(Vector2i::new(1, 0), Direction2D::Down),
+            BridgeAction::Pass
+        );
+        assert_eq!(board.current_bridges().len(), 2);
+    }
+}
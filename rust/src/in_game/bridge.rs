@@ -1,13 +1,17 @@
 use godot::{
-    engine::{BoxContainer, Line2D},
+    engine::{
+        control::MouseFilter, global::MouseButton, BoxContainer, IBoxContainer, InputEvent,
+        InputEventMouseButton, Line2D,
+    },
     prelude::*,
 };
 
+use super::game_map::Direction2D;
 use super::island::Island;
 
 const POS: &str = "pos";
 
-fn order_vector2i(p1: Vector2i, p2: Vector2i) -> (Vector2i, Vector2i) {
+pub fn order_vector2i(p1: Vector2i, p2: Vector2i) -> (Vector2i, Vector2i) {
     let first;
     let second;
     if p1.x != p2.x {
@@ -37,15 +41,29 @@ fn gen_default_color() -> Color {
     Color::from_rgb(0.443, 0.737, 0.988)
 }
 
+/// 对战模式中每个玩家的桥梁颜色
+const PLAYER_COLORS: [(f32, f32, f32); 4] = [
+    (0.988, 0.525, 0.443),
+    (0.443, 0.859, 0.525),
+    (0.788, 0.525, 0.988),
+    (0.988, 0.859, 0.443),
+];
+
 #[derive(GodotClass, Debug)]
 #[class(init, base=BoxContainer)]
 pub struct Bridge {
     first_point: Vector2i,
     second_point: Vector2i,
-    _first_line_name: String,
-    second_line_name: String,
+    /// 从第一个岛屿出发的方向
+    #[init(default = Direction2D::Right)]
+    direction: Direction2D,
+    /// 桥面线段(相对第一个岛屿左上角)，环形地图跨越边界的桥梁会分成两段
+    segments: Vec<(Vector2, Vector2)>,
     scale: f32,
     bridge_count: i32,
+    /// 对战模式中桥梁所属的玩家，-1表示没有
+    #[init(default = -1)]
+    player: i32,
     base: Base<BoxContainer>,
 }
 
@@ -53,83 +71,54 @@ pub struct Bridge {
 impl Bridge {
     #[func]
     pub fn create(p1: Vector2i, p2: Vector2i, scale: f32, bridge_count: i32) -> Gd<Self> {
-        let default_color = gen_default_color();
         let (first, second) = order_vector2i(p1, p2);
-        let offset = scale / 10.0;
-        let line_width = scale / 10.0;
-        let mut l1 = Line2D::new_alloc();
-        let mut l2: Option<Gd<Line2D>> = None;
-        if first.x != second.x {
-            if bridge_count > 1 {
-                l1.add_point(Vector2::new(scale, scale / 2.0 - offset));
-                l1.add_point(Vector2::new(
-                    (p2.x - p1.x) as f32 * scale,
-                    scale / 2.0 - offset,
-                ));
-                let mut l2_t = Line2D::new_alloc();
-                l2_t.add_point(Vector2::new(scale, scale / 2.0 + offset));
-                l2_t.add_point(Vector2::new(
-                    (p2.x - p1.x) as f32 * scale,
-                    scale / 2.0 + offset,
-                ));
-                l2 = Some(l2_t);
-            } else {
-                l1.add_point(Vector2::new(scale, scale / 2.0));
-                l1.add_point(Vector2::new((p2.x - p1.x) as f32 * scale, scale / 2.0));
-            }
-        } else {
-            if bridge_count > 1 {
-                l1.add_point(Vector2::new(scale / 2.0 - offset, scale));
-                l1.add_point(Vector2::new(
-                    scale / 2.0 - offset,
-                    (p2.y - p1.y) as f32 * scale,
-                ));
-                let mut l2_t = Line2D::new_alloc();
-                l2_t.add_point(Vector2::new(scale / 2.0 + offset, scale));
-                l2_t.add_point(Vector2::new(
-                    scale / 2.0 + offset,
-                    (p2.y - p1.y) as f32 * scale,
-                ));
-                l2 = Some(l2_t);
-            } else {
-                l1.add_point(Vector2::new(scale / 2.0, scale));
-                l1.add_point(Vector2::new(scale / 2.0, (p2.y - p1.y) as f32 * scale));
-            }
-        }
-        let name = Self::calc_name(p1, p2);
-        let mut first_line_name = name.clone();
-        first_line_name.push_str("_1");
-        let mut second_line_name = name.clone();
-        second_line_name.push_str("_2");
+        let half = Vector2::new(scale / 2.0, scale / 2.0);
+        let delta = second - first;
+        let second_center = half + Vector2::new(delta.x as f32, delta.y as f32) * scale;
+        let dir = (second_center - half).normalized();
+        let direction = Direction2D::between(first, second).unwrap_or(Direction2D::Right);
+        let segments = PackedVector2Array::from(
+            &[
+                half + dir * (scale / 2.0),
+                second_center - dir * (scale / 2.0),
+            ][..],
+        );
+        Self::create_segments(first, second, direction, segments, scale, bridge_count)
+    }
+    /// 根据桥面线段(相对第一个岛屿左上角，每两个点为一段)创建桥梁，适用于任意网格
+    #[func]
+    pub fn create_segments(
+        first_point: Vector2i,
+        second_point: Vector2i,
+        direction: Direction2D,
+        segments: PackedVector2Array,
+        scale: f32,
+        bridge_count: i32,
+    ) -> Gd<Self> {
+        let segments = segments
+            .as_slice()
+            .chunks_exact(2)
+            .map(|c| (c[0], c[1]))
+            .collect();
         let mut res = Gd::from_init_fn(|base| Bridge {
-            first_point: first,
-            second_point: second,
+            first_point,
+            second_point,
+            direction,
+            segments,
             scale,
-            bridge_count: {
-                if bridge_count > 1 {
-                    2
-                } else {
-                    1
-                }
-            },
-            _first_line_name: first_line_name.clone(),
-            second_line_name: second_line_name.clone(),
+            bridge_count: bridge_count.max(1),
+            player: -1,
             base,
         });
-        res.set_name(name.into());
-        l1.set_name(first_line_name.into());
-        l1.set_default_color(default_color);
-        l1.set_width(line_width);
-        res.add_child(l1.upcast());
-        if l2.is_some() {
-            let mut l2 = l2.unwrap();
-            l2.set_name(second_line_name.into());
-            l2.set_default_color(default_color);
-            l2.set_width(line_width);
-            res.add_child(l2.upcast());
-        }
+        res.set_name(Self::calc_name(first_point, second_point).into());
+        res.set_mouse_filter(MouseFilter::STOP);
+        res.bind_mut().rebuild_lines();
         res
     }
+    #[signal]
+    pub fn bridge_clicked(first_point: Vector2i, direction: Direction2D) {}
+    #[signal]
+    pub fn bridge_right_clicked(first_point: Vector2i, direction: Direction2D) {}
     #[func]
     pub fn calc_name(p1: Vector2i, p2: Vector2i) -> String {
         let (first_point, second_point) = order_vector2i(p1, p2);
@@ -157,46 +146,89 @@ impl Bridge {
     }
     #[func]
     pub fn change_bridge_count(&mut self, count: i32) {
-        if self.bridge_count == count {
+        if self.bridge_count == count || count < 1 {
+            return;
+        }
+        self.bridge_count = count;
+        self.rebuild_lines();
+    }
+    /// 设置桥梁所属的玩家，不叫set_owner以免覆盖`Node.set_owner`
+    #[func]
+    pub fn set_player(&mut self, player: i32) {
+        if self.player == player {
             return;
         }
-        if count > 1 {
-            // 变为双桥
-            let color = gen_default_color();
-            let line_width = self.scale / 10.0;
-            let offset = self.scale / 10.0;
-            let mut l1 = self.base_mut().get_child(0).unwrap().cast::<Line2D>();
-            let mut l1_p1 = l1.get_point_position(0);
-            let mut l1_p2 = l1.get_point_position(1);
-            let l2_p1;
-            let l2_p2;
-            if self.first_point.x != self.second_point.x {
-                l1_p1.y -= offset;
-                l1_p2.y -= offset;
-                l2_p1 = Vector2::new(self.scale, self.scale / 2.0 + offset);
-                l2_p2 = Vector2::new(
-                    (self.second_point.x - self.first_point.x) as f32 * self.scale,
-                    self.scale / 2.0 + offset,
-                );
-            } else {
-                l1_p1.x -= offset;
-                l1_p2.x -= offset;
-                l2_p1 = Vector2::new(self.scale / 2.0 + offset, self.scale);
-                l2_p2 = Vector2::new(
-                    self.scale / 2.0 + offset,
-                    (self.second_point.y - self.first_point.y) as f32 * self.scale,
-                );
+        self.player = player;
+        self.rebuild_lines();
+    }
+    /// 玩家的颜色，player小于0时为默认颜色
+    #[func]
+    pub fn player_color(player: i32) -> Color {
+        if player < 0 {
+            return gen_default_color();
+        }
+        let (r, g, b) = PLAYER_COLORS[player as usize % PLAYER_COLORS.len()];
+        Color::from_rgb(r, g, b)
+    }
+    /// 按桥梁数量重新生成平行的线条
+    fn rebuild_lines(&mut self) {
+        let color = Self::player_color(self.player);
+        let line_width = self.scale / 10.0;
+        let spacing = self.scale / 5.0;
+        let name = Self::calc_name(self.first_point, self.second_point);
+        let children = self.base().get_children();
+        for mut child in children.iter_shared() {
+            self.base_mut().remove_child(child.clone());
+            child.queue_free();
+        }
+        for (seg_i, (start, end)) in self.segments.clone().into_iter().enumerate() {
+            let dir = (end - start).normalized();
+            let normal = Vector2::new(-dir.y, dir.x);
+            for i in 0..self.bridge_count {
+                let offset = normal * ((i as f32 - (self.bridge_count - 1) as f32 / 2.0) * spacing);
+                let mut line = Line2D::new_alloc();
+                line.add_point(start + offset);
+                line.add_point(end + offset);
+                line.set_name(format!("{}_{}_{}", name, seg_i, i + 1).into());
+                line.set_default_color(color);
+                line.set_width(line_width);
+                self.base_mut().add_child(line.upcast());
             }
-            l1.set_point_position(0, l1_p1);
-            l1.set_point_position(1, l1_p2);
-            let mut l2 = Line2D::new_alloc();
-            l2.set_width(line_width);
-            l2.add_point(l2_p1);
-            l2.add_point(l2_p2);
-            l2.set_name(self.second_line_name.clone().into());
-            l2.set_default_color(color);
-            self.base_mut().add_child(l2.upcast());
-            self.bridge_count = 2;
         }
     }
 }
+
+#[godot_api]
+impl IBoxContainer for Bridge {
+    /// 只有两个岛屿之间的桥面可以点击
+    fn has_point(&self, point: Vector2) -> bool {
+        self.segments.iter().any(|(start, end)| {
+            let len = (*end - *start).length();
+            let dir = (*end - *start).normalized();
+            let rel = point - *start;
+            let along = rel.dot(dir);
+            let across = rel.x * dir.y - rel.y * dir.x;
+            along >= 0.0 && along <= len && across.abs() <= self.scale / 4.0
+        })
+    }
+    fn gui_input(&mut self, event: Gd<InputEvent>) {
+        if !event.is_class("InputEventMouseButton".into()) {
+            return;
+        }
+        let event = event.cast::<InputEventMouseButton>();
+        if !event.is_pressed() {
+            return;
+        }
+        let signal = match event.get_button_index() {
+            MouseButton::LEFT => "bridge_clicked",
+            MouseButton::RIGHT => "bridge_right_clicked",
+            _ => return,
+        };
+        let param = &[
+            Variant::from(self.first_point),
+            Variant::from(self.direction),
+        ];
+        self.base_mut().emit_signal(signal.into(), param);
+        self.base_mut().accept_event();
+    }
+}
@@ -0,0 +1,385 @@
+use super::game_map::GameMap;
+use super::puzzle::Puzzle;
+use godot::engine::{ConfigFile, DirAccess, FileAccess, Json};
+use godot::prelude::*;
+use std::collections::HashSet;
+
+/// 本地记录关卡完成情况的文件，每个关卡包一个section，每个关卡的最快用时一个key
+const RECORD_PATH: &'static str = "user://campaign.cfg";
+
+/// 关卡的谜题来源
+#[derive(Debug, Clone, PartialEq)]
+enum LevelSource {
+    /// 直接写在关卡包中的谜题
+    Puzzle(Puzzle),
+    /// 由种子和地图参数生成
+    Seed {
+        seed: i64,
+        width: i32,
+        height: i32,
+        game_mode: i32,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Level {
+    /// 关卡包内唯一，用于记录完成情况
+    id: String,
+    name: String,
+    source: LevelSource,
+    /// 解锁需要先完成的关卡，None表示需要完成上一关
+    requires: Option<Vec<String>>,
+}
+
+/// 关卡包，JSON格式：
+///
+/// ```json
+/// {
+///     "id": "basic",
+///     "name": "入门",
+///     "levels": [
+///         {"id": "1", "name": "第一关", "puzzle": "size 3 1\ngrid\n2 . 2"},
+///         {"id": "2", "puzzle": {"size": [3, 1], "grid": ["1 . 1"]}},
+///         {"id": "3", "seed": 42, "size": [8, 6], "game_mode": 2, "requires": ["1"]}
+///     ]
+/// }
+/// ```
+///
+/// `puzzle`为谜题的文本格式或者JSON格式，见[`Puzzle`]；没有`puzzle`时用`seed`生成。
+/// 关卡按照数组中的顺序排列，没有`requires`时完成上一关后解锁，第一关总是解锁
+#[derive(Debug, Clone, PartialEq)]
+struct LevelPack {
+    id: String,
+    name: String,
+    levels: Vec<Level>,
+}
+
+impl LevelPack {
+    fn from_dict(dict: &Dictionary) -> Result<Self, String> {
+        let id = get_string(dict, "id")?;
+        let name = match dict.get("name") {
+            Some(v) => v.to_string(),
+            None => id.clone(),
+        };
+        let levels: Vec<Level> = dict
+            .get("levels")
+            .ok_or_else(|| "缺少levels".to_string())?
+            .try_to::<VariantArray>()
+            .map_err(|_| "levels必须为数组".to_string())?
+            .iter_shared()
+            .enumerate()
+            .map(|(i, v)| {
+                v.try_to::<Dictionary>()
+                    .map_err(|_| "关卡必须为对象".to_string())
+                    .and_then(|d| Level::from_dict(&d))
+                    .map_err(|e| format!("第{}关：{}", i + 1, e))
+            })
+            .collect::<Result<_, _>>()?;
+        let mut ids = HashSet::new();
+        for level in levels.iter() {
+            if !ids.insert(level.id.as_str()) {
+                return Err(format!("关卡id重复：{}", level.id));
+            }
+        }
+        for level in levels.iter() {
+            for id in level.requires.iter().flatten() {
+                if !ids.contains(id.as_str()) {
+                    return Err(format!("关卡{}需要的关卡不存在：{}", level.id, id));
+                }
+            }
+        }
+        Ok(LevelPack { id, name, levels })
+    }
+    /// 关卡是否已经解锁，completed为已经完成的关卡id
+    fn is_unlocked(&self, index: usize, completed: &HashSet<String>) -> bool {
+        let Some(level) = self.levels.get(index) else {
+            return false;
+        };
+        match &level.requires {
+            Some(requires) => requires.iter().all(|id| completed.contains(id)),
+            None => index == 0 || completed.contains(&self.levels[index - 1].id),
+        }
+    }
+    /// 第一个解锁但是还没有完成的关卡
+    fn next_level(&self, completed: &HashSet<String>) -> Option<usize> {
+        (0..self.levels.len())
+            .find(|i| !completed.contains(&self.levels[*i].id) && self.is_unlocked(*i, completed))
+    }
+}
+
+impl Level {
+    fn from_dict(dict: &Dictionary) -> Result<Self, String> {
+        let id = get_string(dict, "id")?;
+        let name = match dict.get("name") {
+            Some(v) => v.to_string(),
+            None => id.clone(),
+        };
+        let source = match dict.get("puzzle") {
+            Some(v) => match v.try_to::<Dictionary>() {
+                Ok(d) => LevelSource::Puzzle(Puzzle::from_dict(&d)?),
+                Err(_) => LevelSource::Puzzle(Puzzle::from_text(&v.to_string())?),
+            },
+            None => {
+                let size = dict
+                    .get("size")
+                    .ok_or_else(|| "缺少puzzle或者size".to_string())?
+                    .try_to::<VariantArray>()
+                    .map_err(|_| "size必须为[宽, 高]".to_string())?;
+                if size.len() != 2 {
+                    return Err("size必须为[宽, 高]".into());
+                }
+                LevelSource::Seed {
+                    seed: to_i64(dict.get("seed").ok_or_else(|| "缺少seed".to_string())?)?,
+                    width: to_i64(size.get(0))? as i32,
+                    height: to_i64(size.get(1))? as i32,
+                    game_mode: match dict.get("game_mode") {
+                        Some(v) => to_i64(v)? as i32,
+                        None => 1,
+                    },
+                }
+            }
+        };
+        let requires = match dict.get("requires") {
+            Some(v) => Some(
+                v.try_to::<VariantArray>()
+                    .map_err(|_| "requires必须为数组".to_string())?
+                    .iter_shared()
+                    .map(|id| id.to_string())
+                    .collect(),
+            ),
+            None => None,
+        };
+        Ok(Level {
+            id,
+            name,
+            source,
+            requires,
+        })
+    }
+}
+
+fn get_string(dict: &Dictionary, key: &str) -> Result<String, String> {
+    dict.get(key)
+        .map(|v| v.to_string())
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| format!("缺少{}", key))
+}
+
+/// JSON中的数字都是浮点数
+fn to_i64(v: Variant) -> Result<i64, String> {
+    v.try_to::<f64>()
+        .map(|f| f as i64)
+        .map_err(|_| format!("不是数字：{}", v))
+}
+
+/// 闯关模式：加载关卡包，把关卡交给`GameMap`，并在本地记录每一关的完成情况
+#[derive(GodotClass, Debug)]
+#[class(init, base = RefCounted)]
+pub struct Campaign {
+    packs: Vec<LevelPack>,
+    base: Base<RefCounted>,
+}
+
+#[godot_api]
+impl Campaign {
+    /// 加载关卡包文件，id相同的关卡包会被替换，返回错误信息
+    #[func]
+    fn load_pack(&mut self, path: GString) -> GString {
+        let text = FileAccess::get_file_as_string(path.clone());
+        if text.is_empty() {
+            return format!("无法读取关卡包：{}", path).into();
+        }
+        let Ok(dict) = Json::parse_string(text).try_to::<Dictionary>() else {
+            return format!("关卡包格式错误：{}", path).into();
+        };
+        match LevelPack::from_dict(&dict) {
+            Ok(pack) => {
+                match self.packs.iter_mut().find(|p| p.id == pack.id) {
+                    Some(old) => *old = pack,
+                    None => self.packs.push(pack),
+                }
+                GString::new()
+            }
+            Err(e) => format!("{}：{}", path, e).into(),
+        }
+    }
+    /// 加载目录中所有的`.json`关卡包，返回错误信息
+    #[func]
+    fn load_pack_dir(&mut self, dir: GString) -> GString {
+        let mut errors = vec![];
+        let mut files: Vec<String> = DirAccess::get_files_at(dir.clone())
+            .as_slice()
+            .iter()
+            .map(|f| f.to_string())
+            .filter(|f| f.ends_with(".json"))
+            .collect();
+        files.sort();
+        for file in files {
+            let err = self.load_pack(format!("{}/{}", dir, file).into());
+            if !err.is_empty() {
+                errors.push(err.to_string());
+            }
+        }
+        errors.join("\n").into()
+    }
+    #[func]
+    fn pack_count(&self) -> i32 {
+        self.packs.len() as i32
+    }
+    #[func]
+    fn pack_name(&self, pack: i32) -> GString {
+        self.get_pack(pack)
+            .map(|p| p.name.as_str())
+            .unwrap_or_default()
+            .into()
+    }
+    #[func]
+    fn level_count(&self, pack: i32) -> i32 {
+        self.get_pack(pack).map(|p| p.levels.len()).unwrap_or(0) as i32
+    }
+    #[func]
+    fn level_name(&self, pack: i32, level: i32) -> GString {
+        self.get_level(pack, level)
+            .map(|l| l.name.as_str())
+            .unwrap_or_default()
+            .into()
+    }
+    #[func]
+    fn is_unlocked(&self, pack: i32, level: i32) -> bool {
+        let Some(p) = self.get_pack(pack) else {
+            return false;
+        };
+        level >= 0 && p.is_unlocked(level as usize, &Self::completed_levels(p))
+    }
+    #[func]
+    fn is_completed(&self, pack: i32, level: i32) -> bool {
+        self.best_time(pack, level) >= 0.0
+    }
+    /// 最快的完成用时(秒)，没有完成时为-1
+    #[func]
+    fn best_time(&self, pack: i32, level: i32) -> f64 {
+        match (self.get_pack(pack), self.get_level(pack, level)) {
+            (Some(p), Some(l)) => Self::best_time_in(&Self::load_records(), &p.id, &l.id),
+            _ => -1.0,
+        }
+    }
+    /// 第一个解锁但是还没有完成的关卡，全部完成时为-1
+    #[func]
+    fn next_level(&self, pack: i32) -> i32 {
+        self.get_pack(pack)
+            .and_then(|p| p.next_level(&Self::completed_levels(p)))
+            .map(|i| i as i32)
+            .unwrap_or(-1)
+    }
+    /// 把关卡交给地图：内嵌的谜题直接加载，种子关卡设置参数，之后正常reset并生成。返回错误信息
+    #[func]
+    fn setup_map(&self, mut map: Gd<GameMap>, pack: i32, level: i32) -> GString {
+        let Some(l) = self.get_level(pack, level) else {
+            return format!("关卡不存在：{} {}", pack, level).into();
+        };
+        let mut map = map.bind_mut();
+        match &l.source {
+            LevelSource::Puzzle(puzzle) => map.load_puzzle(puzzle),
+            LevelSource::Seed {
+                seed,
+                width,
+                height,
+                game_mode,
+            } => map.use_seed_settings(*width, *height, *game_mode, *seed),
+        }
+        GString::new()
+    }
+    /// 记录完成用时，只保留最快的
+    #[func]
+    fn record_result(&self, pack: i32, level: i32, seconds: f64) {
+        let (Some(p), Some(l)) = (self.get_pack(pack), self.get_level(pack, level)) else {
+            return;
+        };
+        let mut config = Self::load_records();
+        let best = Self::best_time_in(&config, &p.id, &l.id);
+        if best < 0.0 || seconds < best {
+            config.set_value(
+                p.id.as_str().into(),
+                l.id.as_str().into(),
+                Variant::from(seconds),
+            );
+            config.save(RECORD_PATH.into());
+        }
+    }
+    fn get_pack(&self, pack: i32) -> Option<&LevelPack> {
+        usize::try_from(pack).ok().and_then(|i| self.packs.get(i))
+    }
+    fn get_level(&self, pack: i32, level: i32) -> Option<&Level> {
+        let pack = self.get_pack(pack)?;
+        usize::try_from(level).ok().and_then(|i| pack.levels.get(i))
+    }
+    fn completed_levels(pack: &LevelPack) -> HashSet<String> {
+        let config = Self::load_records();
+        pack.levels
+            .iter()
+            .filter(|l| Self::best_time_in(&config, &pack.id, &l.id) >= 0.0)
+            .map(|l| l.id.clone())
+            .collect()
+    }
+    fn load_records() -> Gd<ConfigFile> {
+        let mut config = ConfigFile::new_gd();
+        // 文件不存在时为空记录
+        config.load(RECORD_PATH.into());
+        config
+    }
+    fn best_time_in(config: &Gd<ConfigFile>, pack_id: &str, level_id: &str) -> f64 {
+        if !config.has_section_key(pack_id.into(), level_id.into()) {
+            return -1.0;
+        }
+        config
+            .get_value(pack_id.into(), level_id.into())
+            .try_to::<f64>()
+            .unwrap_or(-1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(id: &str, requires: Option<&[&str]>) -> Level {
+        Level {
+            id: id.into(),
+            name: id.into(),
+            source: LevelSource::Seed {
+                seed: 0,
+                width: 5,
+                height: 5,
+                game_mode: 1,
+            },
+            requires: requires.map(|r| r.iter().map(|s| s.to_string()).collect()),
+        }
+    }
+
+    #[test]
+    fn test_unlock() {
+        let pack = LevelPack {
+            id: "test".into(),
+            name: "test".into(),
+            levels: vec![
+                level("a", None),
+                level("b", None),
+                level("c", Some(&["a"])),
+                level("d", Some(&["b", "c"])),
+            ],
+        };
+        let completed = |ids: &[&str]| ids.iter().map(|s| s.to_string()).collect();
+        let none: HashSet<String> = completed(&[]);
+        assert!(pack.is_unlocked(0, &none));
+        assert!(!pack.is_unlocked(1, &none));
+        assert!(!pack.is_unlocked(4, &none));
+        assert_eq!(pack.next_level(&none), Some(0));
+        let a = completed(&["a"]);
+        assert!(pack.is_unlocked(1, &a));
+        assert!(pack.is_unlocked(2, &a));
+        assert!(!pack.is_unlocked(3, &a));
+        assert_eq!(pack.next_level(&a), Some(1));
+        assert!(pack.is_unlocked(3, &completed(&["a", "b", "c"])));
+        assert_eq!(pack.next_level(&completed(&["a", "b", "c", "d"])), None);
+    }
+}
@@ -0,0 +1,169 @@
+use super::game_map::GameMap;
+use godot::engine::{ConfigFile, Time};
+use godot::prelude::*;
+
+/// 本地记录每日谜题结果的文件
+const RECORD_PATH: &'static str = "user://daily.cfg";
+const RECORD_SECTION: &'static str = "daily";
+/// 混入种子的常量，修改后所有日期的谜题都会改变
+const SEED_SALT: u64 = 0x6c6f_6769_635f_6973;
+/// 从周一到周日的难度
+const WEEKDAY_GAME_MODE: [i32; 7] = [1, 1, 2, 2, 3, 3, 4];
+
+/// 每日谜题的参数，只由日期决定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DailyParams {
+    seed: u64,
+    width: i32,
+    height: i32,
+    game_mode: i32,
+}
+
+impl DailyParams {
+    /// day为从1970-01-01开始的天数
+    fn from_day(day: i64) -> Self {
+        let seed = splitmix64(day as u64 ^ SEED_SALT);
+        // 1970-01-01是星期四
+        let weekday = (day + 3).rem_euclid(7) as usize;
+        let game_mode = WEEKDAY_GAME_MODE[weekday];
+        DailyParams {
+            seed,
+            width: 7 + game_mode + (seed % 3) as i32,
+            height: 6 + game_mode / 2 + ((seed >> 8) % 3) as i32,
+            game_mode,
+        }
+    }
+}
+
+/// 把相邻的整数打散成看起来无关的种子
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// 公历日期到1970-01-01的天数
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// 解析`YYYY-MM-DD`格式的日期，返回到1970-01-01的天数
+fn parse_date(date: &str) -> Option<i64> {
+    let parts: Vec<i64> = date
+        .split('-')
+        .map(|p| p.parse::<i64>().ok())
+        .collect::<Option<_>>()?;
+    let [year, month, day] = parts[..] else {
+        return None;
+    };
+    if !(1..=12).contains(&month) || day < 1 {
+        return None;
+    }
+    // 下个月第一天减去这个月第一天就是这个月的天数
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    let days_in_month = days_from_civil(next_year, next_month, 1) - days_from_civil(year, month, 1);
+    if day > days_in_month {
+        return None;
+    }
+    Some(days_from_civil(year, month, day))
+}
+
+/// 每日谜题：同一天所有玩家得到相同的谜题，不需要联网
+#[derive(GodotClass, Debug)]
+#[class(init, base = RefCounted)]
+pub struct DailyPuzzle {
+    base: Base<RefCounted>,
+}
+
+#[godot_api]
+impl DailyPuzzle {
+    /// 今天(本地时间)的日期，格式为`YYYY-MM-DD`
+    #[func]
+    fn today() -> GString {
+        Time::singleton().get_date_string_from_system()
+    }
+    /// 按照日期设置地图的大小、难度和种子，之后正常reset并生成，返回错误信息
+    #[func]
+    fn setup_map(mut map: Gd<GameMap>, date: GString) -> GString {
+        let Some(day) = parse_date(&date.to_string()) else {
+            return format!("日期格式错误：{}", date).into();
+        };
+        let params = DailyParams::from_day(day);
+        // 其他设置都使用默认值，保证所有玩家一致
+        map.bind_mut().use_seed_settings(
+            params.width,
+            params.height,
+            params.game_mode,
+            params.seed as i64,
+        );
+        GString::new()
+    }
+    /// 记录完成用时，只保留最快的
+    #[func]
+    fn record_result(date: GString, seconds: f64) {
+        let mut config = Self::load_records();
+        let best = Self::best_time_in(&config, &date);
+        if best < 0.0 || seconds < best {
+            config.set_value(RECORD_SECTION.into(), date.into(), Variant::from(seconds));
+            config.save(RECORD_PATH.into());
+        }
+    }
+    #[func]
+    fn is_solved(date: GString) -> bool {
+        Self::best_time(date) >= 0.0
+    }
+    /// 最快的完成用时(秒)，没有完成时为-1
+    #[func]
+    fn best_time(date: GString) -> f64 {
+        Self::best_time_in(&Self::load_records(), &date)
+    }
+    fn load_records() -> Gd<ConfigFile> {
+        let mut config = ConfigFile::new_gd();
+        // 文件不存在时为空记录
+        config.load(RECORD_PATH.into());
+        config
+    }
+    fn best_time_in(config: &Gd<ConfigFile>, date: &GString) -> f64 {
+        if !config.has_section_key(RECORD_SECTION.into(), date.clone().into()) {
+            return -1.0;
+        }
+        config
+            .get_value(RECORD_SECTION.into(), date.clone().into())
+            .try_to::<f64>()
+            .unwrap_or(-1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2000-03-01"), Some(11017));
+        assert_eq!(parse_date("2024-02-29"), Some(19782));
+        assert_eq!(parse_date("2023-02-29"), None);
+        assert_eq!(parse_date("2024-1"), None);
+    }
+    #[test]
+    fn test_daily_params() {
+        let monday = DailyParams::from_day(parse_date("2024-01-01").unwrap());
+        assert_eq!(monday.game_mode, 1);
+        let sunday = DailyParams::from_day(parse_date("2024-01-07").unwrap());
+        assert_eq!(sunday.game_mode, 4);
+        assert_eq!(monday, DailyParams::from_day(19723));
+        assert_ne!(monday.seed, DailyParams::from_day(19724).seed);
+    }
+}
@@ -1,11 +1,19 @@
+use super::board::{BridgeRules, IslandState};
+use super::bridge::{order_vector2i, Bridge};
+use super::generator::{hide_clues, Generator};
 use super::island::Island;
-use godot::engine::Sprite2D;
+use super::puzzle::{Puzzle, PuzzleIsland};
+use super::shape::Shape;
+use super::solver::Solver;
+use crate::common::settings::{Settings, SETTING_CHANGED};
+use godot::engine::{Image, Json, Line2D, Sprite2D};
 use godot::prelude::*;
-use rand::Rng;
-use std::collections::HashSet;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::collections::{HashMap, HashSet};
 
 /// 搭桥动作
-#[derive(GodotConvert, Debug, Var, Export)]
+#[derive(GodotConvert, Debug, Clone, Copy, PartialEq, Eq, Var, Export)]
 #[godot(via = GString)]
 pub enum BridgeAction {
     /// 拆桥
@@ -16,10 +24,24 @@ pub enum BridgeAction {
     Single = 3,
     /// 双桥
     Double = 4,
+    /// 三桥
+    Triple = 5,
+}
+
+impl BridgeAction {
+    /// 设置为count座桥之后的渲染动作
+    pub fn from_count(count: i32) -> Self {
+        match count {
+            0 => BridgeAction::Remove,
+            1 => BridgeAction::Single,
+            2 => BridgeAction::Double,
+            _ => BridgeAction::Triple,
+        }
+    }
 }
 
 /// 方向
-#[derive(GodotConvert, Debug, Var, Export)]
+#[derive(GodotConvert, Debug, Clone, Copy, PartialEq, Eq, Hash, Var, Export)]
 #[godot(via = GString)]
 pub enum Direction2D {
     /// 上
@@ -30,31 +52,213 @@ pub enum Direction2D {
     Left = 3,
     /// 右
     Right = 4,
+    /// 右上(六边形网格)
+    UpRight = 5,
+    /// 左下(六边形网格)
+    DownLeft = 6,
+    /// 左上(对角线变体)
+    UpLeft = 7,
+    /// 右下(对角线变体)
+    DownRight = 8,
 }
 
-#[derive(Debug)]
-struct RandInsertVec {
-    pub value: Vec<Vector2i>,
-    pub limit_rng: usize,
-    rng: rand::rngs::ThreadRng,
+impl Direction2D {
+    /// 方向数量，也是岛屿bridge_states的长度
+    pub const COUNT: usize = 8;
+    pub fn to_vector(self) -> Vector2i {
+        match self {
+            Direction2D::Up => Vector2i::UP,
+            Direction2D::Down => Vector2i::DOWN,
+            Direction2D::Left => Vector2i::LEFT,
+            Direction2D::Right => Vector2i::RIGHT,
+            Direction2D::UpRight => Vector2i::new(1, -1),
+            Direction2D::DownLeft => Vector2i::new(-1, 1),
+            Direction2D::UpLeft => Vector2i::new(-1, -1),
+            Direction2D::DownRight => Vector2i::new(1, 1),
+        }
+    }
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction2D::Up => Direction2D::Down,
+            Direction2D::Down => Direction2D::Up,
+            Direction2D::Left => Direction2D::Right,
+            Direction2D::Right => Direction2D::Left,
+            Direction2D::UpRight => Direction2D::DownLeft,
+            Direction2D::DownLeft => Direction2D::UpRight,
+            Direction2D::UpLeft => Direction2D::DownRight,
+            Direction2D::DownRight => Direction2D::UpLeft,
+        }
+    }
+    /// 文本格式中的名字
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction2D::Up => "Up",
+            Direction2D::Down => "Down",
+            Direction2D::Left => "Left",
+            Direction2D::Right => "Right",
+            Direction2D::UpRight => "UpRight",
+            Direction2D::DownLeft => "DownLeft",
+            Direction2D::UpLeft => "UpLeft",
+            Direction2D::DownRight => "DownRight",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        ALL_DIRECTIONS.iter().copied().find(|d| d.name() == name)
+    }
+    /// 在bridge_states中的下标
+    pub fn index(self) -> usize {
+        match self {
+            Direction2D::Up => 0,
+            Direction2D::Right => 1,
+            Direction2D::Down => 2,
+            Direction2D::Left => 3,
+            Direction2D::UpRight => 4,
+            Direction2D::DownLeft => 5,
+            Direction2D::UpLeft => 6,
+            Direction2D::DownRight => 7,
+        }
+    }
+    /// 从p1指向p2的方向，不在同一条直线上时为None
+    pub fn between(p1: Vector2i, p2: Vector2i) -> Option<Self> {
+        let delta = p2 - p1;
+        if delta == Vector2i::ZERO {
+            return None;
+        }
+        if delta.x != 0 && delta.y != 0 && delta.x.abs() != delta.y.abs() {
+            return None;
+        }
+        let step = Vector2i::new(delta.x.signum(), delta.y.signum());
+        ALL_DIRECTIONS
+            .iter()
+            .copied()
+            .find(|d| d.to_vector() == step)
+    }
+    /// bridge_states中该方向的桥梁数量
+    pub fn state_of(self, states: &PackedInt32Array) -> i32 {
+        states.as_slice().get(self.index()).copied().unwrap_or(0)
+    }
+    pub fn set_state_of(self, states: &mut PackedInt32Array, count: i32) {
+        if states.len() < Self::COUNT {
+            states.resize(Self::COUNT);
+        }
+        states.as_mut_slice()[self.index()] = count;
+    }
 }
-impl RandInsertVec {
-    fn new() -> Self {
-        RandInsertVec {
-            value: vec![],
-            limit_rng: 0,
-            rng: rand::thread_rng(),
+
+const ALL_DIRECTIONS: [Direction2D; Direction2D::COUNT] = [
+    Direction2D::Up,
+    Direction2D::Right,
+    Direction2D::Down,
+    Direction2D::Left,
+    Direction2D::UpRight,
+    Direction2D::DownLeft,
+    Direction2D::UpLeft,
+    Direction2D::DownRight,
+];
+const SQUARE_DIRECTIONS: [Direction2D; 4] = [
+    Direction2D::Up,
+    Direction2D::Right,
+    Direction2D::Down,
+    Direction2D::Left,
+];
+const HEX_DIRECTIONS: [Direction2D; 6] = [
+    Direction2D::Up,
+    Direction2D::UpRight,
+    Direction2D::Right,
+    Direction2D::Down,
+    Direction2D::DownLeft,
+    Direction2D::Left,
+];
+const DIAGONAL_DIRECTIONS: [Direction2D; 8] = [
+    Direction2D::Up,
+    Direction2D::UpRight,
+    Direction2D::Right,
+    Direction2D::DownRight,
+    Direction2D::Down,
+    Direction2D::DownLeft,
+    Direction2D::Left,
+    Direction2D::UpLeft,
+];
+
+/// 地图网格类型
+#[derive(GodotConvert, Debug, Clone, Copy, PartialEq, Eq, Var, Export)]
+#[godot(via = GString)]
+pub enum MapVariant {
+    /// 正方形网格，4个方向
+    Square = 1,
+    /// 六边形网格(轴坐标，尖顶朝上)，6个方向
+    Hex = 2,
+    /// 正方形网格，允许对角线搭桥，8个方向
+    Diagonal = 3,
+}
+
+impl MapVariant {
+    /// 文本格式中的名字
+    pub fn name(self) -> &'static str {
+        match self {
+            MapVariant::Square => "Square",
+            MapVariant::Hex => "Hex",
+            MapVariant::Diagonal => "Diagonal",
         }
     }
-    fn insert(&mut self, p: Vector2i) {
-        if self.limit_rng < self.value.len() {
-            self.value.push(p);
-            return;
+    pub fn from_name(name: &str) -> Option<Self> {
+        [MapVariant::Square, MapVariant::Hex, MapVariant::Diagonal]
+            .into_iter()
+            .find(|v| v.name() == name)
+    }
+    /// 可以搭桥的方向
+    pub fn directions(self) -> &'static [Direction2D] {
+        match self {
+            MapVariant::Square => &SQUARE_DIRECTIONS,
+            MapVariant::Hex => &HEX_DIRECTIONS,
+            MapVariant::Diagonal => &DIAGONAL_DIRECTIONS,
+        }
+    }
+    /// 绘制网格线的方向
+    pub fn grid_directions(self) -> &'static [Direction2D] {
+        match self {
+            MapVariant::Square | MapVariant::Diagonal => &SQUARE_DIRECTIONS,
+            MapVariant::Hex => &HEX_DIRECTIONS,
         }
-        //随机插入
-        let index = self.rng.gen_range(self.limit_rng..=self.value.len());
-        self.value.insert(index, p);
-        return;
+    }
+    /// 格子中心的像素坐标
+    pub fn cell_center(self, pos: Vector2i, scale: f32) -> Vector2 {
+        let (x, y) = match self {
+            MapVariant::Square | MapVariant::Diagonal => (pos.x as f32, pos.y as f32),
+            MapVariant::Hex => (
+                pos.x as f32 + pos.y as f32 / 2.0,
+                pos.y as f32 * 3f32.sqrt() / 2.0,
+            ),
+        };
+        Vector2::new(x + 0.5, y + 0.5) * scale
+    }
+    /// 像素坐标所在的格子，`cell_center`的逆运算
+    pub fn cell_at(self, pixel: Vector2, scale: f32) -> Vector2i {
+        let p = pixel * (1.0 / scale);
+        if self != MapVariant::Hex {
+            return Vector2i::new(p.x.floor() as i32, p.y.floor() as i32);
+        }
+        let y = ((p.y - 0.5) / (3f32.sqrt() / 2.0)).round() as i32;
+        let x = (p.x - 0.5 - y as f32 / 2.0).round() as i32;
+        // 六边形的边界不是直线，在周围找最近的格子中心
+        let mut best = Vector2i::new(x, y);
+        let mut best_distance = f32::MAX;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let cell = Vector2i::new(x + dx, y + dy);
+                let distance = (self.cell_center(cell, 1.0) - p).length_squared();
+                if distance < best_distance {
+                    best = cell;
+                    best_distance = distance;
+                }
+            }
+        }
+        best
+    }
+    /// 方向在屏幕上的单位向量
+    pub fn pixel_direction(self, direction: Direction2D) -> Vector2 {
+        (self.cell_center(direction.to_vector(), 1.0) - self.cell_center(Vector2i::ZERO, 1.0))
+            .normalized()
     }
 }
 
@@ -79,525 +283,1277 @@ pub struct GameMap {
     pub islands: Dictionary,
     #[init(default = vec![])]
     pub islands_pos: Vec<Vector2i>,
-    #[init(default = vec![])]
-    pub islands_gate_pos: Vec<Vector2i>,
-    #[init(default = vec![])]
-    pub able_to_gen_islands: Vec<Vector2i>,
-    #[init(default = vec![])]
-    pub bridge_points: Vec<Vector2i>,
     #[init(default = HashSet::new())]
     pub user_bridge_points: HashSet<Vector2i>,
+    #[init(default = HashSet::new())]
+    pub user_bridge_crossings: HashSet<Vector2i>,
+    /// 障碍物，不能放置岛屿，桥梁也不能经过
+    #[init(default = HashSet::new())]
+    pub obstacles: HashSet<Vector2i>,
+    /// 地图形状，属于地图的格子，None时为整个矩形
+    #[init(default = None)]
+    pub mask: Option<HashSet<Vector2i>>,
+    /// 颜色数量，大于1时每种颜色的岛屿各自组成一个网络
+    #[init(default = 1)]
+    #[export]
+    pub color_count: i32,
+    /// 隐藏最大桥梁数量的岛屿比例，只会在保持唯一解时隐藏
+    #[init(default = 0.0)]
+    #[export]
+    pub hidden_clue_ratio: f32,
     #[init(default = 1)]
     #[export]
     pub game_mode: i32,
-    #[init(default = rand::thread_rng())]
-    rng: rand::rngs::ThreadRng,
+    /// 网格类型
+    #[init(default = MapVariant::Square)]
+    #[export]
+    pub variant: MapVariant,
+    /// 环形地图，桥梁可以从一边穿出并从对边进入
+    #[init(default = false)]
+    #[export]
+    pub wrap: bool,
+    /// 两个岛屿之间最多的桥梁数量
+    #[init(default = DEFAULT_MAX_BRIDGES_PER_LINK)]
+    #[export]
+    pub max_bridges_per_link: i32,
+    /// 每个格子的像素大小
+    #[init(default = 30.0)]
+    #[export]
+    pub item_scale: f32,
+    /// 编辑器模式：搭桥不受岛屿数量限制，可以放置岛屿和障碍物
+    #[init(default = false)]
+    #[export]
+    pub editor_mode: bool,
+    /// 轮流搭桥的玩家数量，大于1时为同屏对战模式
+    #[init(default = 1)]
+    #[export]
+    pub player_count: i32,
+    /// 对战模式中当前回合的玩家，从0开始
+    #[init(default = 0)]
+    current_player: i32,
+    /// 对战模式中每座桥最后由哪个玩家修改，见`link_key`
+    #[init(default = HashMap::new())]
+    link_owners: HashMap<(Vector2i, Direction2D), i32>,
+    /// 对战模式中完成岛屿的玩家
+    #[init(default = HashMap::new())]
+    island_owners: HashMap<Vector2i, i32>,
+    /// 生成地图使用的随机数，见`set_seed`。使用算法固定的ChaCha8，
+    /// 不同平台和依赖版本中相同的种子生成相同的地图，`StdRng`的算法不保证不变
+    #[init(default = ChaCha8Rng::from_entropy())]
+    rng: ChaCha8Rng,
+    #[init(default = None)]
+    preview_line: Option<Gd<Line2D>>,
+    /// 玩家操作的历史，记录操作之前的桥梁数量，用于撤销
+    #[init(default = vec![])]
+    history: Vec<(Vector2i, Direction2D, i32)>,
+    /// 谜题的答案，见`Solver::solve`，外层None表示还没有求解
+    #[init(default = None)]
+    solution: Option<Option<Vec<(Vector2i, Direction2D, i32)>>>,
+    /// 谜题是否只有一个解，None表示还没有判断，见`has_unique_solution`
+    #[init(default = None)]
+    unique_solution: Option<bool>,
     base: Base<Sprite2D>,
 }
 
-const BRIDGE_STATE: &'static str = "bridge_state";
+const BRIDGE_STATES: &'static str = "bridge_states";
 const MAX_BRIDGE_COUNT: &'static str = "max_bridge_count";
 const CURRENT_BRIDGE_COUNT: &'static str = "current_bridge_count";
 const CHANGE_BRIDGE_COUNT: &'static str = "change_bridge_count";
 const RENDER_BRIDGE: &'static str = "render_bridge";
+const PUZZLE_SOLVED: &'static str = "puzzle_solved";
+const USER_MOVED: &'static str = "user_moved";
+const MOVE_UNDONE: &'static str = "move_undone";
+const HINT_USED: &'static str = "hint_used";
+const MAP_GENERATED: &'static str = "map_generated";
+const PUZZLE_EDITED: &'static str = "puzzle_edited";
+const TURN_CHANGED: &'static str = "turn_changed";
+const ISLAND_CLAIMED: &'static str = "island_claimed";
+const COLOR_ID: &'static str = "color_id";
+const CLUE_HIDDEN: &'static str = "clue_hidden";
+const PREVIEW_NAME: &'static str = "preview";
+/// 两个岛屿之间默认最多的桥梁数量
+const DEFAULT_MAX_BRIDGES_PER_LINK: i32 = 2;
+/// 支持的最多桥梁数量(三桥变体)
+const MAX_BRIDGES_PER_LINK: i32 = 3;
+/// 生成唯一解的谜题最多尝试的次数
+const MAX_GENERATE_ATTEMPTS: usize = 20;
 
 /// 生成阶段
 #[godot_api]
 impl GameMap {
     #[signal]
-    pub fn render_bridge(src_island: Gd<Island>, target_island: Gd<Island>, action: BridgeAction) {}
+    pub fn render_bridge(
+        src_island: Gd<Island>,
+        target_island: Gd<Island>,
+        action: BridgeAction,
+        direction: Direction2D,
+    ) {
+    }
+    #[signal]
+    pub fn puzzle_solved() {}
+    /// 玩家改变了桥梁数量，mistake表示这一步出错，见`is_mistake`
+    #[signal]
+    pub fn user_moved(src_pos: Vector2i, direction: Direction2D, count: i32, mistake: bool) {}
+    #[signal]
+    pub fn move_undone() {}
+    #[signal]
+    pub fn hint_used() {}
+    /// 地图生成或者加载完成
+    #[signal]
+    pub fn map_generated(width: i32, height: i32, game_mode: i32) {}
+    /// 编辑器模式中地图被修改
+    #[signal]
+    pub fn puzzle_edited() {}
+    /// 对战模式中轮到player搭桥
+    #[signal]
+    pub fn turn_changed(player: i32) {}
+    /// 对战模式中player完成了岛屿，岛屿不再完成时player为-1
+    #[signal]
+    pub fn island_claimed(pos: Vector2i, player: i32) {}
+    /// 点击桥梁或者拖动到相邻岛屿：增加一座桥，达到上限之后拆掉所有桥
     #[func]
-    fn user_gen_bridge(&mut self, src_pos: Vector2i, direction: Direction2D) -> BridgeAction {
-        let mut target_pos = src_pos.clone();
-        let mut src_island = self.islands.get(src_pos).unwrap().to::<Gd<Island>>();
-        let mut src_island_bridge_state = src_island.get(BRIDGE_STATE.into()).to::<Vector4i>();
-        let src_island_max_bridge_count = src_island.get(MAX_BRIDGE_COUNT.into()).to::<i32>();
-        let mut src_island_current_bridge_count =
-            src_island.get(CURRENT_BRIDGE_COUNT.into()).to::<i32>();
-        let mut src_has_bridge;
-        loop {
-            match direction {
-                Direction2D::Up => {
-                    target_pos += Vector2i::UP;
-                    src_has_bridge = src_island_bridge_state.x;
-                }
-                Direction2D::Down => {
-                    target_pos += Vector2i::DOWN;
-                    src_has_bridge = src_island_bridge_state.z;
-                }
-                Direction2D::Left => {
-                    target_pos += Vector2i::LEFT;
-                    src_has_bridge = src_island_bridge_state.w;
-                }
-                Direction2D::Right => {
-                    target_pos += Vector2i::RIGHT;
-                    src_has_bridge = src_island_bridge_state.y;
-                }
-            }
-            if self.user_bridge_points.contains(&target_pos) && src_has_bridge == 0
-                || !self.is_in_map(&target_pos)
-            {
-                // 已经被别的岛屿搭桥，或者不在地图范围内
-                return BridgeAction::Pass;
+    pub fn user_gen_bridge(&mut self, src_pos: Vector2i, direction: Direction2D) -> BridgeAction {
+        match self.gen_bridge_count(src_pos, direction) {
+            Some((target_pos, count)) => {
+                self.apply_user_move(src_pos, target_pos, direction, count)
             }
-            if !self.islands.contains_key(target_pos) {
-                // 没有岛屿
-                continue;
-            }
-            let mut target_island = self.islands.get(target_pos).unwrap().to::<Gd<Island>>();
-            let mut target_island_bridge_state =
-                target_island.get(BRIDGE_STATE.into()).to::<Vector4i>();
-            let target_island_max_bridge_count =
-                target_island.get(MAX_BRIDGE_COUNT.into()).to::<i32>();
-            let mut target_island_current_bridge_count =
-                target_island.get(CURRENT_BRIDGE_COUNT.into()).to::<i32>();
-            if src_island_max_bridge_count == src_island_current_bridge_count
-                || target_island_max_bridge_count == target_island_current_bridge_count
-                || src_has_bridge == 2
-            {
-                if src_has_bridge == 0 {
-                    return BridgeAction::Pass;
+            None => BridgeAction::Pass,
+        }
+    }
+    /// 减少一座桥，没有桥时无事发生
+    #[func]
+    fn user_sub_bridge(&mut self, src_pos: Vector2i, direction: Direction2D) -> BridgeAction {
+        let Some(target_pos) = self.find_link_target(src_pos, direction) else {
+            return BridgeAction::Pass;
+        };
+        let src_has_bridge = self.get_bridge_count(src_pos, direction);
+        if src_has_bridge == 0 {
+            return BridgeAction::Pass;
+        }
+        self.apply_user_move(src_pos, target_pos, direction, src_has_bridge - 1)
+    }
+    /// 直接设置桥梁数量，超出岛屿容量时无事发生
+    #[func]
+    pub fn user_set_bridge(
+        &mut self,
+        src_pos: Vector2i,
+        direction: Direction2D,
+        count: i32,
+    ) -> BridgeAction {
+        if !(0..=self.max_bridges_per_link).contains(&count) {
+            return BridgeAction::Pass;
+        }
+        let Some(target_pos) = self.find_link_target(src_pos, direction) else {
+            return BridgeAction::Pass;
+        };
+        let src_has_bridge = self.get_bridge_count(src_pos, direction);
+        if count > src_has_bridge
+            && !self.can_add_bridge(src_pos, target_pos, count - src_has_bridge)
+        {
+            return BridgeAction::Pass;
+        }
+        self.apply_user_move(src_pos, target_pos, direction, count)
+    }
+    /// 点击桥梁本身时循环切换桥梁数量
+    #[func]
+    fn user_cycle_bridge(&mut self, p1: Vector2i, p2: Vector2i) -> BridgeAction {
+        match Direction2D::between(p1, p2) {
+            Some(direction) => self.user_gen_bridge(p1, direction),
+            None => BridgeAction::Pass,
+        }
+    }
+    /// 棋盘在地图中的像素范围
+    #[func]
+    fn board_rect(&self) -> Rect2 {
+        let half = Vector2::new(self.item_scale, self.item_scale) / 2.0;
+        let corners = [
+            Vector2i::new(0, 0),
+            Vector2i::new(self.width - 1, 0),
+            Vector2i::new(0, self.height - 1),
+            Vector2i::new(self.width - 1, self.height - 1),
+        ];
+        let mut min = self.grid_to_pixel(corners[0]);
+        let mut max = min;
+        for c in corners.iter() {
+            let p = self.grid_to_pixel(*c);
+            min = Vector2::new(min.x.min(p.x), min.y.min(p.y));
+            max = Vector2::new(max.x.max(p.x), max.y.max(p.y));
+        }
+        Rect2::new(min - half, max - min + half * 2.0)
+    }
+    /// 岛屿节点(左上角)的像素坐标
+    #[func]
+    fn cell_position(&self, pos: Vector2i) -> Vector2 {
+        self.grid_to_pixel(pos) - Vector2::new(self.item_scale, self.item_scale) / 2.0
+    }
+    /// 地图中的像素坐标所在的格子
+    #[func]
+    fn cell_at(&self, pixel: Vector2) -> Vector2i {
+        self.variant.cell_at(pixel, self.item_scale)
+    }
+    /// 网格线，每两个点为一条线段
+    #[func]
+    fn grid_lines(&self) -> PackedVector2Array {
+        let mut res = PackedVector2Array::new();
+        for x in 0..self.width {
+            for y in 0..self.height {
+                let p = Vector2i::new(x, y);
+                if !self.shape().is_in_bounds(&p) {
+                    continue;
                 }
-                match direction {
-                    Direction2D::Up => {
-                        src_island_bridge_state.x = 0;
-                        target_island_bridge_state.z = 0;
-                    }
-                    Direction2D::Down => {
-                        src_island_bridge_state.z = 0;
-                        target_island_bridge_state.x = 0;
+                for direction in self.variant.grid_directions().iter() {
+                    let next = p + direction.to_vector();
+                    // 每条线段只画一次
+                    if next.y < p.y || next.y == p.y && next.x < p.x {
+                        continue;
                     }
-                    Direction2D::Left => {
-                        src_island_bridge_state.w = 0;
-                        target_island_bridge_state.y = 0;
+                    // 环形地图也不画跨越边界的线
+                    if self.shape().is_in_bounds(&next) {
+                        res.push(self.grid_to_pixel(p));
+                        res.push(self.grid_to_pixel(next));
                     }
-                    Direction2D::Right => {
-                        src_island_bridge_state.y = 0;
-                        target_island_bridge_state.w = 0;
-                    }
-                }
-                // 拆桥
-                for p in Self::calc_points(Some(src_pos), target_pos, true).iter() {
-                    self.user_bridge_points.remove(p);
                 }
-                // 更新状态
-                src_island_current_bridge_count -= src_has_bridge;
-                target_island_current_bridge_count -= src_has_bridge;
-                src_island.set(
-                    CURRENT_BRIDGE_COUNT.into(),
-                    Variant::from(src_island_current_bridge_count),
-                );
-                src_island.set(BRIDGE_STATE.into(), Variant::from(src_island_bridge_state));
-                target_island.set(
-                    CURRENT_BRIDGE_COUNT.into(),
-                    Variant::from(target_island_current_bridge_count),
-                );
-                target_island.set(
-                    BRIDGE_STATE.into(),
-                    Variant::from(target_island_bridge_state),
-                );
-                // 触发signal
-                let s_arg = &[src_island.to_variant()];
-                src_island.emit_signal(CHANGE_BRIDGE_COUNT.into(), s_arg);
-                let t_arg = &[target_island.to_variant()];
-                target_island.emit_signal(CHANGE_BRIDGE_COUNT.into(), t_arg);
-                self.base_mut().emit_signal(
-                    RENDER_BRIDGE.into(),
-                    &[
-                        Variant::from(src_island),
-                        Variant::from(target_island),
-                        Variant::from(BridgeAction::Remove),
-                    ],
-                );
-                return BridgeAction::Remove;
             }
-            match direction {
-                Direction2D::Up => {
-                    src_island_bridge_state.x += 1;
-                    target_island_bridge_state.z += 1;
-                }
-                Direction2D::Down => {
-                    src_island_bridge_state.z += 1;
-                    target_island_bridge_state.x += 1;
-                }
-                Direction2D::Left => {
-                    src_island_bridge_state.w += 1;
-                    target_island_bridge_state.y += 1;
-                }
-                Direction2D::Right => {
-                    src_island_bridge_state.y += 1;
-                    target_island_bridge_state.w += 1;
-                }
+        }
+        res
+    }
+    /// 创建两个岛屿之间的桥梁节点，位置已经设置好
+    #[func]
+    fn create_bridge(
+        &self,
+        src_pos: Vector2i,
+        target_pos: Vector2i,
+        direction: Direction2D,
+        bridge_count: i32,
+    ) -> Gd<Bridge> {
+        let (first, second) = order_vector2i(src_pos, target_pos);
+        let first_direction = if first == src_pos {
+            direction
+        } else {
+            direction.opposite()
+        };
+        let origin = self.cell_position(first);
+        let mut segments = PackedVector2Array::new();
+        for (start, end) in self.link_segments(first, second, first_direction) {
+            segments.push(start - origin);
+            segments.push(end - origin);
+        }
+        let mut bridge = Bridge::create_segments(
+            first,
+            second,
+            first_direction,
+            segments,
+            self.item_scale,
+            bridge_count,
+        );
+        bridge.set_name(self.bridge_name(src_pos, target_pos, direction).into());
+        bridge.set_position(origin);
+        bridge
+    }
+    /// 桥梁节点的名字，环形地图中两个岛屿可能从两个方向相连，所以带上方向
+    #[func]
+    fn bridge_name(
+        &self,
+        src_pos: Vector2i,
+        target_pos: Vector2i,
+        direction: Direction2D,
+    ) -> String {
+        let (first, _) = order_vector2i(src_pos, target_pos);
+        let first_direction = if first == src_pos {
+            direction
+        } else {
+            direction.opposite()
+        };
+        format!(
+            "{}_{}",
+            Bridge::calc_name(src_pos, target_pos),
+            first_direction.index()
+        )
+    }
+    /// 拖动到rel_pos(相对岛屿左上角)时是否可以搭桥
+    #[func]
+    fn preview_target(&self, src_pos: Vector2i, rel_pos: Vector2) -> bool {
+        match self.resolve_direction(rel_pos) {
+            Some(direction) => {
+                matches!(self.check_move(src_pos, direction, false), (Some(_), true))
+            }
+            None => false,
+        }
+    }
+    /// 显示搭桥预览，吸附到目标岛屿，不能搭桥时显示为阻塞状态
+    #[func]
+    fn show_preview(&mut self, src_pos: Vector2i, rel_pos: Vector2, is_sub: bool) {
+        let Some(direction) = self.resolve_direction(rel_pos) else {
+            self.clear_preview();
+            return;
+        };
+        let (target_pos, legal) = self.check_move(src_pos, direction, is_sub);
+        let half = self.item_scale / 2.0;
+        let dir = self.variant.pixel_direction(direction);
+        let src_center = self.grid_to_pixel(src_pos);
+        let mut start = src_center + dir * half;
+        let end = match target_pos {
+            Some(p) => {
+                // 跨越边界的桥梁只预览第一段
+                let (seg_start, seg_end) = self.link_segments(src_pos, p, direction)[0];
+                start = seg_start;
+                seg_end
             }
-            // 搭桥
-            for p in Self::calc_points(Some(src_pos), target_pos, true).iter() {
-                self.user_bridge_points.insert(*p);
+            None => {
+                let drag = rel_pos - Vector2::new(half, half);
+                src_center + dir * drag.dot(dir).max(half)
             }
-            // 更新状态
-            src_island_current_bridge_count += 1;
-            target_island_current_bridge_count += 1;
-            src_island.set(
-                CURRENT_BRIDGE_COUNT.into(),
-                Variant::from(src_island_current_bridge_count),
-            );
-            src_island.set(BRIDGE_STATE.into(), Variant::from(src_island_bridge_state));
-            target_island.set(
-                CURRENT_BRIDGE_COUNT.into(),
-                Variant::from(target_island_current_bridge_count),
-            );
-            target_island.set(
-                BRIDGE_STATE.into(),
-                Variant::from(target_island_bridge_state),
-            );
-            // 触发signal
-            let s_arg = &[src_island.to_variant()];
-            src_island.emit_signal(CHANGE_BRIDGE_COUNT.into(), s_arg);
-            let t_arg = &[target_island.to_variant()];
-            target_island.emit_signal(CHANGE_BRIDGE_COUNT.into(), t_arg);
-            let action;
-            let render_action;
-            if src_has_bridge == 0 {
-                action = BridgeAction::Single;
-                render_action = BridgeAction::Single;
+        };
+        let color = if legal {
+            Color::from_rgba(1.0, 1.0, 1.0, 0.8)
+        } else {
+            Color::from_rgba(0.9, 0.2, 0.2, 0.8)
+        };
+        let mut line = match self.preview_line.clone() {
+            Some(line) => line,
+            None => {
+                let mut line = Line2D::new_alloc();
+                line.set_name(PREVIEW_NAME.into());
+                line.set_width(3.0);
+                line.add_point(start);
+                line.add_point(end);
+                self.base_mut().add_child(line.clone().upcast());
+                self.preview_line = Some(line.clone());
+                line
+            }
+        };
+        line.set_default_color(color);
+        line.set_point_position(0, start);
+        line.set_point_position(1, end);
+    }
+    /// 移除搭桥预览，返回之前是否存在预览
+    #[func]
+    fn clear_preview(&mut self) -> bool {
+        let Some(mut line) = self.preview_line.take() else {
+            return false;
+        };
+        self.base_mut().remove_child(line.clone().upcast());
+        line.queue_free();
+        true
+    }
+    /// 结束拖动，根据拖动位置搭桥或拆桥
+    #[func]
+    fn finish_preview(
+        &mut self,
+        src_pos: Vector2i,
+        rel_pos: Vector2,
+        is_sub: bool,
+    ) -> BridgeAction {
+        if !self.clear_preview() {
+            // 取消搭桥
+            return BridgeAction::Pass;
+        }
+        let Some(direction) = self.resolve_direction(rel_pos) else {
+            return BridgeAction::Pass;
+        };
+        if is_sub {
+            self.user_sub_bridge(src_pos, direction)
+        } else {
+            self.user_gen_bridge(src_pos, direction)
+        }
+    }
+    /// 岛屿在direction方向上的桥梁数量
+    #[func]
+    pub fn get_bridge_count(&self, pos: Vector2i, direction: Direction2D) -> i32 {
+        match self.islands.get(pos) {
+            Some(v) => {
+                let island = v.to::<Gd<Island>>();
+                direction.state_of(&island.get(BRIDGE_STATES.into()).to::<PackedInt32Array>())
+            }
+            None => 0,
+        }
+    }
+    /// 把两个岛屿之间的桥梁数量设置为count，更新状态并触发signal
+    fn apply_bridge_count(
+        &mut self,
+        src_pos: Vector2i,
+        target_pos: Vector2i,
+        direction: Direction2D,
+        count: i32,
+    ) -> BridgeAction {
+        let mut src_island = self.islands.get(src_pos).unwrap().to::<Gd<Island>>();
+        let mut target_island = self.islands.get(target_pos).unwrap().to::<Gd<Island>>();
+        let mut src_island_bridge_state = src_island
+            .get(BRIDGE_STATES.into())
+            .to::<PackedInt32Array>();
+        let mut target_island_bridge_state = target_island
+            .get(BRIDGE_STATES.into())
+            .to::<PackedInt32Array>();
+        let src_has_bridge = direction.state_of(&src_island_bridge_state);
+        if src_has_bridge == count {
+            return BridgeAction::Pass;
+        }
+        direction.set_state_of(&mut src_island_bridge_state, count);
+        direction
+            .opposite()
+            .set_state_of(&mut target_island_bridge_state, count);
+        self.mark_user_bridge(src_pos, target_pos, direction, src_has_bridge, count);
+        // 更新状态
+        let diff = count - src_has_bridge;
+        let src_island_current_bridge_count =
+            src_island.get(CURRENT_BRIDGE_COUNT.into()).to::<i32>() + diff;
+        let target_island_current_bridge_count =
+            target_island.get(CURRENT_BRIDGE_COUNT.into()).to::<i32>() + diff;
+        src_island.set(
+            CURRENT_BRIDGE_COUNT.into(),
+            Variant::from(src_island_current_bridge_count),
+        );
+        src_island.set(BRIDGE_STATES.into(), Variant::from(src_island_bridge_state));
+        target_island.set(
+            CURRENT_BRIDGE_COUNT.into(),
+            Variant::from(target_island_current_bridge_count),
+        );
+        target_island.set(
+            BRIDGE_STATES.into(),
+            Variant::from(target_island_bridge_state),
+        );
+        // 触发signal
+        let s_arg = &[src_island.to_variant()];
+        src_island.emit_signal(CHANGE_BRIDGE_COUNT.into(), s_arg);
+        let t_arg = &[target_island.to_variant()];
+        target_island.emit_signal(CHANGE_BRIDGE_COUNT.into(), t_arg);
+        let action = BridgeAction::from_count(count);
+        self.base_mut().emit_signal(
+            RENDER_BRIDGE.into(),
+            &[
+                Variant::from(src_island),
+                Variant::from(target_island),
+                Variant::from(action),
+                Variant::from(direction),
+            ],
+        );
+        action
+    }
+    /// 玩家的操作：记录到历史中，触发user_moved，然后检查是否完成
+    fn apply_user_move(
+        &mut self,
+        src_pos: Vector2i,
+        target_pos: Vector2i,
+        direction: Direction2D,
+        count: i32,
+    ) -> BridgeAction {
+        let before = self.get_bridge_count(src_pos, direction);
+        if self.is_versus() && before != count {
+            // 显示桥梁之前记录所有者
+            let key = link_key(src_pos, target_pos, direction);
+            if count == 0 {
+                self.link_owners.remove(&key);
             } else {
-                action = BridgeAction::Double;
-                render_action = BridgeAction::Double;
+                self.link_owners.insert(key, self.current_player);
+            }
+        }
+        let action = self.apply_bridge_count(src_pos, target_pos, direction, count);
+        if action == BridgeAction::Pass {
+            return action;
+        }
+        self.history.push((src_pos, direction, before));
+        let mistake = !self.editor_mode
+            && count > before
+            && self.is_mistake(src_pos, target_pos, direction, count);
+        self.base_mut().emit_signal(
+            USER_MOVED.into(),
+            &[
+                Variant::from(src_pos),
+                Variant::from(direction),
+                Variant::from(count),
+                Variant::from(mistake),
+            ],
+        );
+        if self.is_versus() {
+            self.claim_islands(&[src_pos, target_pos]);
+            self.next_turn();
+        }
+        self.check_solved();
+        action
+    }
+    /// 编辑器模式中不会完成，改为触发puzzle_edited
+    fn check_solved(&mut self) {
+        if self.editor_mode {
+            self.emit_edited();
+        } else if self.is_solved() {
+            self.base_mut().emit_signal(PUZZLE_SOLVED.into(), &[]);
+        }
+    }
+    /// 谜题的答案，第一次使用时求解，无解或者太复杂时为None
+    fn solution(&mut self) -> Option<Vec<(Vector2i, Direction2D, i32)>> {
+        if self.solution.is_none() {
+            self.solution = Some(Solver::solve(&self.to_puzzle()));
+        }
+        self.solution.clone().flatten()
+    }
+    /// 谜题是否只有一个解，第一次使用时判断。生成器不保证唯一解，多解时不能只用一个解判断对错
+    fn has_unique_solution(&mut self) -> bool {
+        if self.unique_solution.is_none() {
+            let count = Solver::count_solutions(&self.to_puzzle(), 2);
+            self.unique_solution = Some(count == Some(1));
+        }
+        self.unique_solution.unwrap()
+    }
+    /// 搭到count座桥之后是否出错：唯一解时超出答案中的数量为出错，
+    /// 多解时当前的桥梁不包含在任何一个解中才算出错，太复杂时不算出错
+    fn is_mistake(
+        &mut self,
+        src_pos: Vector2i,
+        target_pos: Vector2i,
+        direction: Direction2D,
+        count: i32,
+    ) -> bool {
+        if self.has_unique_solution() {
+            return self
+                .solution_count(src_pos, target_pos, direction)
+                .is_some_and(|c| count > c);
+        }
+        Solver::solve_containing(&self.to_puzzle(), &self.current_bridges()) == Some(None)
+    }
+    /// 提示使用的答案：唯一解时为答案，多解时为包含当前桥梁的解，这样不会拆掉正确的桥，
+    /// 当前的桥梁已经无解时才使用答案
+    fn hint_solution(&mut self) -> Option<Vec<(Vector2i, Direction2D, i32)>> {
+        if !self.has_unique_solution() {
+            let res = Solver::solve_containing(&self.to_puzzle(), &self.current_bridges());
+            if let Some(Some(solution)) = res {
+                return Some(solution);
+            }
+        }
+        self.solution()
+    }
+    /// 答案中两个岛屿之间的桥梁数量
+    fn solution_count(
+        &mut self,
+        src_pos: Vector2i,
+        target_pos: Vector2i,
+        direction: Direction2D,
+    ) -> Option<i32> {
+        self.solution()?
+            .iter()
+            .find(|(pos, d, _)| {
+                (*pos == src_pos && *d == direction)
+                    || (*pos == target_pos && *d == direction.opposite())
+            })
+            .map(|(_, _, count)| *count)
+    }
+    /// 撤销上一步操作，返回是否有可以撤销的操作，对战模式中不能撤销
+    #[func]
+    pub fn undo(&mut self) -> bool {
+        if self.is_versus() {
+            return false;
+        }
+        let Some((src_pos, direction, count)) = self.history.pop() else {
+            return false;
+        };
+        let Some(target_pos) = self.find_link_target(src_pos, direction) else {
+            return false;
+        };
+        self.apply_bridge_count(src_pos, target_pos, direction, count);
+        self.base_mut().emit_signal(MOVE_UNDONE.into(), &[]);
+        self.check_solved();
+        true
+    }
+    /// 提示：按照答案修正一座桥，先拆掉多余的桥再搭缺少的桥，返回是否修正了桥，
+    /// 对战模式中不能提示
+    #[func]
+    pub fn hint(&mut self) -> bool {
+        if self.is_versus() {
+            return false;
+        }
+        let Some(solution) = self.hint_solution() else {
+            return false;
+        };
+        for removing in [true, false] {
+            for (src_pos, direction, count) in solution.iter().copied() {
+                let current = self.get_bridge_count(src_pos, direction);
+                if current == count || (current > count) != removing {
+                    continue;
+                }
+                let Some(target_pos) = self.find_link_target(src_pos, direction) else {
+                    continue;
+                };
+                self.history.push((src_pos, direction, current));
+                self.apply_bridge_count(src_pos, target_pos, direction, count);
+                self.base_mut().emit_signal(HINT_USED.into(), &[]);
+                self.check_solved();
+                return true;
+            }
+        }
+        false
+    }
+    /// 是否已经完成：所有岛屿的桥梁数量都满足要求(隐藏数量的岛屿除外)，
+    /// 并且每种颜色的岛屿连成一个网络
+    #[func]
+    pub fn is_solved(&self) -> bool {
+        BridgeRules::is_solved(self)
+    }
+    /// 对战模式中当前回合的玩家
+    #[func]
+    fn current_player(&self) -> i32 {
+        self.current_player
+    }
+    /// 对战模式中玩家完成的岛屿数量
+    #[func]
+    fn player_score(&self, player: i32) -> i32 {
+        self.island_owners
+            .values()
+            .filter(|p| **p == player)
+            .count() as i32
+    }
+    /// 分数最高的玩家，平局或者不是对战模式时为-1
+    #[func]
+    fn winner(&self) -> i32 {
+        let scores: Vec<i32> = (0..self.player_count)
+            .map(|p| self.player_score(p))
+            .collect();
+        let Some(best) = scores.iter().max() else {
+            return -1;
+        };
+        let mut leaders = (0..self.player_count).filter(|p| scores[*p as usize] == *best);
+        match (leaders.next(), leaders.next()) {
+            (Some(p), None) if self.is_versus() => p,
+            _ => -1,
+        }
+    }
+    /// 对战模式中最后修改这座桥的玩家，没有时为-1
+    #[func]
+    fn bridge_owner(&self, src_pos: Vector2i, direction: Direction2D) -> i32 {
+        let Some(target_pos) = self.find_link_target(src_pos, direction) else {
+            return -1;
+        };
+        self.link_owners
+            .get(&link_key(src_pos, target_pos, direction))
+            .copied()
+            .unwrap_or(-1)
+    }
+    /// 对战模式中完成岛屿的玩家，没有时为-1
+    #[func]
+    fn island_owner(&self, pos: Vector2i) -> i32 {
+        self.island_owners.get(&pos).copied().unwrap_or(-1)
+    }
+    fn is_versus(&self) -> bool {
+        self.player_count > 1 && !self.editor_mode
+    }
+    /// 桥梁数量刚好满足要求的岛屿归当前玩家，不再满足时失去所有者
+    fn claim_islands(&mut self, positions: &[Vector2i]) {
+        for pos in positions {
+            let island = self.islands.get(*pos).unwrap().to::<Gd<Island>>();
+            let complete = !island.get(CLUE_HIDDEN.into()).to::<bool>()
+                && island.get(CURRENT_BRIDGE_COUNT.into()).to::<i32>()
+                    == island.get(MAX_BRIDGE_COUNT.into()).to::<i32>();
+            let player = match (complete, self.island_owners.contains_key(pos)) {
+                (true, false) => self.current_player,
+                (false, true) => -1,
+                _ => continue,
+            };
+            if complete {
+                self.island_owners.insert(*pos, player);
+            } else {
+                self.island_owners.remove(pos);
             }
             self.base_mut().emit_signal(
-                RENDER_BRIDGE.into(),
-                &[
-                    Variant::from(src_island),
-                    Variant::from(target_island),
-                    Variant::from(render_action),
-                ],
+                ISLAND_CLAIMED.into(),
+                &[Variant::from(*pos), Variant::from(player)],
             );
-            return action;
         }
     }
+    fn next_turn(&mut self) {
+        self.current_player = (self.current_player + 1) % self.player_count;
+        let player = self.current_player;
+        self.base_mut()
+            .emit_signal(TURN_CHANGED.into(), &[Variant::from(player)]);
+    }
+    #[func]
+    pub fn create(width: i32, height: i32) -> Gd<Self> {
+        Self::create_variant(width, height, MapVariant::Square)
+    }
     #[func]
-    fn create(width: i32, height: i32) -> Gd<Self> {
+    fn create_variant(width: i32, height: i32, variant: MapVariant) -> Gd<Self> {
         Gd::from_init_fn(|base| Self {
             width,
             height,
             is_ready: false,
-            max_bridge_count: Self::calc_max_bridge_count(1, width, height),
+            max_bridge_count: Generator::max_island_count(1, width * height),
             islands: dict! {},
             islands_pos: vec![],
-            islands_gate_pos: vec![],
-            able_to_gen_islands: vec![],
-            bridge_points: vec![],
             user_bridge_points: HashSet::new(),
+            user_bridge_crossings: HashSet::new(),
+            obstacles: HashSet::new(),
+            mask: None,
+            color_count: 1,
+            hidden_clue_ratio: 0.0,
             game_mode: 1,
-            rng: rand::thread_rng(),
+            variant,
+            wrap: false,
+            max_bridges_per_link: DEFAULT_MAX_BRIDGES_PER_LINK,
+            item_scale: 30.0,
+            editor_mode: false,
+            player_count: 1,
+            current_player: 0,
+            link_owners: HashMap::new(),
+            island_owners: HashMap::new(),
+            rng: ChaCha8Rng::from_entropy(),
+            preview_line: None,
+            history: vec![],
+            solution: None,
+            unique_solution: None,
             base,
         })
     }
-    /// 生成岛屿
+    /// 生成唯一解的谜题，再按照hidden_clue_ratio隐藏数量，见`Generator::generate_unique`，
+    /// 返回生成失败时的信息，失败时is_ready为false
     #[func]
-    fn gen_island(&mut self) -> GString {
-        if self.get_is_ready() {
-            return "生成已完成".into();
+    pub fn generate(&mut self) -> GString {
+        self.reset();
+        let shape = Shape {
+            width: self.width,
+            height: self.height,
+            variant: self.variant,
+            wrap: self.wrap,
+            mask: self.mask.as_ref(),
+        };
+        let res = Generator::new(
+            shape,
+            self.game_mode,
+            self.color_count,
+            self.max_bridges_per_link,
+            &mut self.rng,
+        )
+        .generate_unique(MAX_GENERATE_ATTEMPTS);
+        match res {
+            Ok(mut puzzle) => {
+                hide_clues(&mut puzzle, self.hidden_clue_ratio, &mut self.rng);
+                self.load_puzzle(&puzzle);
+                GString::new()
+            }
+            Err(e) => e.into(),
         }
-        if self.islands_pos.len() == 0 {
-            // 初始化第一个节点
-            let first_point = Vector2i::new(
-                self.rng.gen_range(0..self.get_width()),
-                self.rng.gen_range(0..self.get_height()),
-            );
-            let first_island = Island::create(first_point);
-            self.link_island(None, first_point, first_island);
-            return GString::new();
-        }
-        let src = self.select_random_island();
-        if src.is_none() {
-            return "已经没有可生成节点".into();
-        }
-        let (mut src_position, mut index) = src.unwrap();
-        let mut valid_next_points = self.calc_valid_next_point(src_position);
-        while valid_next_points.is_empty() && !self.able_to_gen_islands.is_empty() {
-            self.able_to_gen_islands.remove(index);
-            // godot_print!("{:?}无法生成，重新选择节点", src_position);
-            let src = self.select_random_island();
-            if src.is_none() {
-                return "已经没有可生成节点".into();
+    }
+    /// 设置随机数种子，相同的种子和参数会生成相同的地图，在`reset`之前调用
+    #[func]
+    pub fn set_seed(&mut self, seed: i64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed as u64);
+    }
+    /// 除了大小和难度之外都使用默认设置，并设置种子，用于每日谜题和关卡，之后正常reset并生成。
+    /// 单人模式，不是编辑器
+    pub fn use_seed_settings(&mut self, width: i32, height: i32, game_mode: i32, seed: i64) {
+        self.width = width;
+        self.height = height;
+        self.game_mode = game_mode;
+        self.variant = MapVariant::Square;
+        self.wrap = false;
+        self.mask = None;
+        self.color_count = 1;
+        self.hidden_clue_ratio = 0.0;
+        self.max_bridges_per_link = DEFAULT_MAX_BRIDGES_PER_LINK;
+        self.player_count = 1;
+        self.editor_mode = false;
+        self.set_seed(seed);
+    }
+    /// `use_seed_settings`之后`generate`得到的谜题，不需要引擎，用于快照测试
+    pub fn seeded_puzzle(
+        width: i32,
+        height: i32,
+        game_mode: i32,
+        seed: i64,
+    ) -> Result<Puzzle, String> {
+        let shape = Shape {
+            width,
+            height,
+            variant: MapVariant::Square,
+            wrap: false,
+            mask: None,
+        };
+        let mut rng = ChaCha8Rng::seed_from_u64(seed as u64);
+        Generator::new(shape, game_mode, 1, DEFAULT_MAX_BRIDGES_PER_LINK, &mut rng)
+            .generate_unique(MAX_GENERATE_ATTEMPTS)
+    }
+    /// 标记地图已经可以开始游戏
+    fn finish_generation(&mut self) {
+        self.set_is_ready(true);
+        let args = [
+            Variant::from(self.width),
+            Variant::from(self.height),
+            Variant::from(self.game_mode),
+        ];
+        self.base_mut().emit_signal(MAP_GENERATED.into(), &args);
+    }
+    /// 使用设置中的地图参数，并在设置修改时更新，之后正常reset并生成
+    #[func]
+    fn use_settings(&mut self, mut settings: Gd<Settings>) {
+        for key in settings.bind().keys().as_slice() {
+            let value = settings.bind().get_setting(key.clone());
+            self.apply_setting(key.to_string().as_str(), value);
+        }
+        let this = self.base().clone();
+        settings.connect(
+            SETTING_CHANGED.into(),
+            Callable::from_object_method(&this, "on_setting_changed"),
+        );
+    }
+    /// 生成之后修改地图参数会破坏当前的地图，在下一局生效
+    #[func]
+    fn on_setting_changed(&mut self, key: GString, value: Variant) {
+        if !self.is_ready {
+            self.apply_setting(key.to_string().as_str(), value);
+        }
+    }
+    /// 设置中的值已经检查过范围
+    fn apply_setting(&mut self, key: &str, value: Variant) {
+        match key {
+            "map_size" => {
+                let size = value.try_to::<Vector2i>().unwrap_or_default();
+                self.width = size.x;
+                self.height = size.y;
             }
-            (src_position, index) = src.unwrap();
-            valid_next_points = self.calc_valid_next_point(src_position);
-        }
-        if valid_next_points.is_empty() {
-            self.set_is_ready(true);
-            return "已经没有可生成节点".into();
-        }
-        let rindex = {
-            let gen_per = self.islands_pos.len() as f32 / self.max_bridge_count as f32;
-            if gen_per < 0.95 {
-                let b = self.rng.gen_bool(0.05 * self.game_mode as f64);
-                self.weighted_random_index(valid_next_points.len(), b)
-            } else {
-                self.rng.gen_range(0..valid_next_points.len())
+            "game_mode" => self.game_mode = value.try_to().unwrap_or(1),
+            "max_bridges_per_link" => {
+                self.max_bridges_per_link = value.try_to().unwrap_or(DEFAULT_MAX_BRIDGES_PER_LINK)
             }
-        };
-        let next_point = valid_next_points[rindex];
-        let next_island;
-        if let Some(v) = self.get_islands().get(next_point) {
-            next_island = v.to();
-        } else {
-            next_island = Island::create(next_point);
+            "map_variant" => {
+                self.variant =
+                    MapVariant::from_name(&value.to_string()).unwrap_or(MapVariant::Square)
+            }
+            "map_wrap" => self.wrap = value.try_to().unwrap_or(false),
+            "color_count" => self.color_count = value.try_to().unwrap_or(1),
+            "hidden_clue_ratio" => {
+                self.hidden_clue_ratio = value.try_to::<f64>().unwrap_or(0.0) as f32
+            }
+            "map_item_scale" => self.item_scale = value.try_to::<f64>().unwrap_or(30.0) as f32,
+            _ => {}
         }
-        self.link_island(Some(src_position), next_point, next_island);
-        GString::new()
     }
     #[func]
     fn reset(&mut self) -> bool {
         self.set_is_ready(false);
         self.islands.clear();
-        self.able_to_gen_islands.clear();
-        self.islands_gate_pos.clear();
-        self.bridge_points.clear();
         self.islands_pos.clear();
         self.user_bridge_points.clear();
+        self.user_bridge_crossings.clear();
+        self.history.clear();
+        self.solution = None;
+        self.unique_solution = None;
+        self.current_player = 0;
+        self.link_owners.clear();
+        self.island_owners.clear();
         // self.game_mode = game_mode;
-        self.set_max_bridge_count(Self::calc_max_bridge_count(
+        self.max_bridges_per_link = self.max_bridges_per_link.clamp(1, MAX_BRIDGES_PER_LINK);
+        self.color_count = self.color_count.max(1);
+        self.obstacles.clear();
+        self.set_max_bridge_count(Generator::max_island_count(
             self.get_game_mode(),
-            self.get_width(),
-            self.get_height(),
+            self.shape().area(),
         ));
         true
     }
-    fn weighted_random_index(&mut self, n: usize, more_weight: bool) -> usize {
-        let weights: Vec<f32>;
-        if more_weight {
-            // 指数权重
-            weights = (0..n).map(|i| (-(i as f32)).exp()).collect();
-        } else {
-            // 线性权重
-            weights = (0..n).map(|i| 1.0 / (i as f32 * 0.8 + 1.0)).collect();
+    /// 障碍物的位置，用于绘制
+    #[func]
+    fn obstacle_positions(&self) -> Array<Vector2i> {
+        let mut res = Array::new();
+        for p in self.obstacles.iter() {
+            res.push(*p);
         }
-
-        let total_weight = weights.iter().sum::<f32>();
-        let rand_v = self.rng.gen_range(0.0..total_weight);
-        let mut cumulative_weight = 0.0;
-        for (i, &weight) in weights.iter().enumerate() {
-            cumulative_weight += weight;
-            if rand_v < cumulative_weight {
-                return i;
+        res
+    }
+    /// 从文本设置地图形状，`_`和空格不属于地图，其他字符属于地图，返回错误信息
+    #[func]
+    fn set_mask_text(&mut self, text: GString) -> GString {
+        let mut mask = HashSet::new();
+        let mut width = 0;
+        let mut height = 0;
+        for (y, line) in text.to_string().lines().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                if c != '_' && c != ' ' {
+                    mask.insert(Vector2i::new(x as i32, y as i32));
+                }
+                width = width.max(x as i32 + 1);
             }
+            height = y as i32 + 1;
         }
-        n
+        self.apply_mask(width, height, mask)
     }
-    fn select_random_island(&mut self) -> Option<(Vector2i, usize)> {
-        if self.able_to_gen_islands.is_empty() {
-            return None;
+    /// 从图片设置地图形状，每个像素一个格子，不透明的像素属于地图，返回错误信息
+    #[func]
+    fn set_mask_image(&mut self, image: Gd<Image>) -> GString {
+        let mut mask = HashSet::new();
+        for x in 0..image.get_width() {
+            for y in 0..image.get_height() {
+                if image.get_pixel(x, y).a > 0.5 {
+                    mask.insert(Vector2i::new(x, y));
+                }
+            }
         }
-        let index = self.rng.gen_range(0..self.able_to_gen_islands.len());
-        let island = self.able_to_gen_islands.get(index).unwrap();
-        Some((island.clone(), index))
+        self.apply_mask(image.get_width(), image.get_height(), mask)
     }
-    fn link_island(
-        &mut self,
-        from_pos: Option<Vector2i>,
-        current_pos: Vector2i,
-        mut island: Gd<Island>,
-    ) {
-        self.fill_conditions(from_pos, current_pos);
-        if from_pos.is_some() {
-            let bridge_count = {
-                if self.rng.gen_bool(0.55 - 0.02 * self.game_mode as f64) {
-                    2
-                } else {
-                    1
-                }
-            };
-            let count_prop = "max_bridge_count";
-            let mut from_island: Gd<Island> = self
-                .islands
-                .get(from_pos.unwrap())
-                .unwrap()
-                .to::<Gd<Island>>();
-            let from_count = from_island.get(count_prop.into()).to::<i32>() + bridge_count;
-            from_island.set(count_prop.into(), Variant::from(from_count));
-            let current_count = island.get(count_prop.into()).to::<i32>() + bridge_count;
-            island.set(count_prop.into(), Variant::from(current_count));
-        }
-        self.get_islands().set(current_pos, island);
-    }
-    fn calc_max_bridge_count(game_mode: i32, w: i32, h: i32) -> i32 {
-        let n = {
-            if game_mode > 2 {
-                0.25
-            } else {
-                0.18
+    /// 恢复为矩形地图
+    #[func]
+    fn clear_mask(&mut self) {
+        self.mask = None;
+    }
+    fn apply_mask(&mut self, width: i32, height: i32, mask: HashSet<Vector2i>) -> GString {
+        if mask.is_empty() {
+            return "地图形状为空".into();
+        }
+        self.width = width;
+        self.height = height;
+        self.mask = Some(mask);
+        GString::new()
+    }
+    /// 导出为文本格式，见`Puzzle`
+    #[func]
+    fn to_puzzle_text(&self) -> GString {
+        self.to_puzzle().to_text().into()
+    }
+    /// 从文本格式加载谜题，代替生成，返回错误信息
+    #[func]
+    fn load_puzzle_text(&mut self, text: GString) -> GString {
+        match Puzzle::from_text(&text.to_string()) {
+            Ok(puzzle) => {
+                self.load_puzzle(&puzzle);
+                GString::new()
             }
+            Err(e) => e.into(),
+        }
+    }
+    /// 导出为JSON格式，见`Puzzle`
+    #[func]
+    fn to_puzzle_json(&self) -> GString {
+        Json::stringify(self.to_puzzle().to_dict().to_variant())
+    }
+    /// 从JSON格式加载谜题，代替生成，返回错误信息
+    #[func]
+    fn load_puzzle_json(&mut self, text: GString) -> GString {
+        let Ok(dict) = Json::parse_string(text).try_to::<Dictionary>() else {
+            return "JSON格式错误".into();
         };
-        (w as f32 * h as f32 * n) as i32
+        match Puzzle::from_dict(&dict) {
+            Ok(puzzle) => {
+                self.load_puzzle(&puzzle);
+                GString::new()
+            }
+            Err(e) => e.into(),
+        }
     }
-    fn calc_island_gate_pos(&self, island_pos: Vector2i) -> Vec<Vector2i> {
-        let mut res = vec![];
-        if island_pos.x > 0 {
-            res.push(Vector2i::new(island_pos.x - 1, island_pos.y));
+    /// 编辑器：清空岛屿、桥梁和障碍物，保留地图大小等参数
+    #[func]
+    fn editor_new(&mut self) {
+        self.reset();
+        self.set_is_ready(true);
+        self.emit_edited();
+    }
+    /// 编辑器：在空格子放置数量为1的岛屿，返回新的岛屿，需要加入场景树；
+    /// 已经有岛屿时移除岛屿和它的桥梁，返回null
+    #[func]
+    fn editor_toggle_island(&mut self, pos: Vector2i) -> Option<Gd<Island>> {
+        if self.islands.contains_key(pos) {
+            self.editor_remove_island(pos);
+            return None;
         }
-        if island_pos.x < self.get_width() - 1 {
-            res.push(Vector2i::new(island_pos.x + 1, island_pos.y));
+        if !self.shape().is_in_bounds(&pos)
+            || self.obstacles.contains(&pos)
+            || self.user_bridge_points.contains(&pos)
+        {
+            return None;
         }
-        if island_pos.y > 0 {
-            res.push(Vector2i::new(island_pos.x, island_pos.y - 1));
+        let mut island = Island::create(pos);
+        island.set(MAX_BRIDGE_COUNT.into(), Variant::from(1));
+        self.islands_pos.push(pos);
+        self.islands.set(pos, island.clone());
+        // 历史中的操作可能经过这个格子
+        self.history.clear();
+        self.emit_edited();
+        Some(island)
+    }
+    /// 编辑器：放置或者移除障碍物，岛屿和桥梁经过的格子不能放置，返回是否修改了
+    #[func]
+    fn editor_toggle_obstacle(&mut self, pos: Vector2i) -> bool {
+        if self.obstacles.remove(&pos) {
+            self.emit_edited();
+            return true;
         }
-        if island_pos.y < self.get_height() - 1 {
-            res.push(Vector2i::new(island_pos.x, island_pos.y + 1));
+        if !self.shape().is_in_bounds(&pos)
+            || self.islands.contains_key(pos)
+            || self.user_bridge_points.contains(&pos)
+        {
+            return false;
         }
-        res
+        self.obstacles.insert(pos);
+        self.history.clear();
+        self.emit_edited();
+        true
+    }
+    /// 编辑器：设置岛屿需要的桥梁数量，小于0时隐藏数量，返回是否修改了
+    #[func]
+    fn editor_set_clue(&mut self, pos: Vector2i, count: i32) -> bool {
+        let Some(v) = self.islands.get(pos) else {
+            return false;
+        };
+        let max_count = self.max_bridges_per_link * self.variant.directions().len() as i32;
+        if count > max_count {
+            return false;
+        }
+        let mut island = v.to::<Gd<Island>>();
+        if count < 0 {
+            island.set(CLUE_HIDDEN.into(), Variant::from(true));
+        } else {
+            island.set(CLUE_HIDDEN.into(), Variant::from(false));
+            island.set(MAX_BRIDGE_COUNT.into(), Variant::from(count));
+        }
+        let arg = &[island.to_variant()];
+        island.emit_signal(CHANGE_BRIDGE_COUNT.into(), arg);
+        self.emit_edited();
+        true
     }
-    fn calc_points(
-        from: Option<Vector2i>,
-        current: Vector2i,
-        exclude_endpoint: bool,
-    ) -> Vec<Vector2i> {
-        if from.is_none() {
-            if exclude_endpoint {
-                return vec![];
+    /// 编辑器：把每个岛屿需要的桥梁数量设置为当前画出的桥梁数量，返回修改的岛屿数量
+    #[func]
+    fn editor_derive_clues(&mut self) -> i32 {
+        let mut changed = 0;
+        for p in self.islands_pos.clone() {
+            let island = self.islands.get(p).unwrap().to::<Gd<Island>>();
+            let count = island.get(CURRENT_BRIDGE_COUNT.into()).to::<i32>();
+            let hidden = island.get(CLUE_HIDDEN.into()).to::<bool>();
+            if hidden || island.get(MAX_BRIDGE_COUNT.into()).to::<i32>() != count {
+                let mut island = island;
+                island.set(CLUE_HIDDEN.into(), Variant::from(false));
+                island.set(MAX_BRIDGE_COUNT.into(), Variant::from(count));
+                let arg = &[island.to_variant()];
+                island.emit_signal(CHANGE_BRIDGE_COUNT.into(), arg);
+                changed += 1;
             }
-            return vec![current];
         }
-        let from = from.unwrap();
-        if exclude_endpoint && from == current {
-            return vec![];
+        if changed > 0 {
+            self.emit_edited();
         }
-        let mut res = vec![];
-        if from.x == current.x {
-            let mut min_y = from.y.min(current.y);
-            let mut max_y = from.y.max(current.y);
-            if exclude_endpoint {
-                min_y += 1;
-                max_y -= 1;
-                if min_y > max_y {
-                    return vec![];
-                }
+        changed
+    }
+    /// 编辑器：检查当前谜题是否有解、是否唯一，见`Solver::describe`
+    #[func]
+    fn editor_check(&self) -> Dictionary {
+        let (solutions, message) = Solver::describe(&self.to_puzzle());
+        dict! {
+            "solutions": solutions,
+            "unique": solutions == 1,
+            "message": message,
+        }
+    }
+    fn editor_remove_island(&mut self, pos: Vector2i) {
+        for direction in self.variant.directions() {
+            if self.get_bridge_count(pos, *direction) == 0 {
+                continue;
             }
-            for y in min_y..=max_y {
-                res.push(Vector2i::new(from.x, y));
+            if let Some(target_pos) = self.find_link_target(pos, *direction) {
+                self.apply_bridge_count(pos, target_pos, *direction, 0);
             }
-        } else if from.y == current.y {
-            let mut min_x = from.x.min(current.x);
-            let mut max_x = from.x.max(current.x);
-            if exclude_endpoint {
-                min_x += 1;
-                max_x -= 1;
-                if min_x > max_x {
-                    return vec![];
+        }
+        if let Some(v) = self.islands.remove(pos) {
+            v.to::<Gd<Island>>().queue_free();
+        }
+        self.islands_pos.retain(|p| *p != pos);
+        self.history.clear();
+        self.emit_edited();
+    }
+    /// 地图被修改之后答案需要重新求解
+    fn emit_edited(&mut self) {
+        self.solution = None;
+        self.unique_solution = None;
+        self.base_mut().emit_signal(PUZZLE_EDITED.into(), &[]);
+    }
+    pub fn to_puzzle(&self) -> Puzzle {
+        let islands = self
+            .islands_pos
+            .iter()
+            .map(|p| {
+                let island = self.islands.get(*p).unwrap().to::<Gd<Island>>();
+                PuzzleIsland {
+                    pos: *p,
+                    count: island.get(MAX_BRIDGE_COUNT.into()).to::<i32>(),
+                    color_id: island.get(COLOR_ID.into()).to::<i32>(),
+                    hidden: island.get(CLUE_HIDDEN.into()).to::<bool>(),
                 }
-            }
-            for x in min_x..=max_x {
-                res.push(Vector2i::new(x, from.y));
-            }
+            })
+            .collect();
+        Puzzle {
+            width: self.width,
+            height: self.height,
+            variant: self.variant,
+            wrap: self.wrap,
+            max_bridges_per_link: self.max_bridges_per_link,
+            islands,
+            obstacles: self.obstacles.iter().copied().collect(),
+            holes: self.shape().holes(),
+        }
+    }
+    pub fn load_puzzle(&mut self, puzzle: &Puzzle) {
+        self.width = puzzle.width;
+        self.height = puzzle.height;
+        self.variant = puzzle.variant;
+        self.wrap = puzzle.wrap;
+        self.max_bridges_per_link = puzzle.max_bridges_per_link;
+        self.mask = if puzzle.holes.is_empty() {
+            None
         } else {
-            godot_error!("不可能的情况");
-            return vec![];
+            Some(
+                self.shape()
+                    .rect_cells()
+                    .into_iter()
+                    .filter(|p| !puzzle.holes.contains(p))
+                    .collect(),
+            )
+        };
+        self.color_count = puzzle.islands.iter().map(|i| i.color_id).max().unwrap_or(0) + 1;
+        self.reset();
+        self.obstacles = puzzle.obstacles.iter().copied().collect();
+        for puzzle_island in puzzle.islands.iter() {
+            let mut island = Island::create(puzzle_island.pos);
+            island.set(MAX_BRIDGE_COUNT.into(), Variant::from(puzzle_island.count));
+            island.set(COLOR_ID.into(), Variant::from(puzzle_island.color_id));
+            island.set(CLUE_HIDDEN.into(), Variant::from(puzzle_island.hidden));
+            self.islands_pos.push(puzzle_island.pos);
+            self.islands.set(puzzle_island.pos, island);
         }
-        res
+        self.finish_generation();
     }
-    /// 从一个岛屿出发，可以生成的另一个岛屿
-    fn calc_valid_next_point(&mut self, point: Vector2i) -> Vec<Vector2i> {
-        let mut result = RandInsertVec::new();
-        let mut has_up = true;
-        let mut has_down = true;
-        let mut has_left = true;
-        let mut has_right = true;
-        let mut offset = 1;
+    /// 两个岛屿之间的桥面线段(像素坐标)，跨越边界时分成两段，分别画到地图边缘
+    fn link_segments(
+        &self,
+        from: Vector2i,
+        to: Vector2i,
+        direction: Direction2D,
+    ) -> Vec<(Vector2, Vector2)> {
+        let half_step =
+            (self.grid_to_pixel(direction.to_vector()) - self.grid_to_pixel(Vector2i::ZERO)) / 2.0;
+        let trim = self.variant.pixel_direction(direction) * (self.item_scale / 2.0);
+        let mut res = vec![];
+        let mut start = self.grid_to_pixel(from) + trim;
+        let mut p = from;
         loop {
-            if !has_up && !has_down && !has_left && !has_right {
-                return result.value;
+            let raw = p + direction.to_vector();
+            let next = self.shape().wrap_pos(raw);
+            if raw != next {
+                // 跨越边界，分成两段
+                res.push((start, self.grid_to_pixel(p) + half_step));
+                start = self.grid_to_pixel(next) - half_step;
             }
-            if has_up {
-                let p = Vector2i::new(point.x, point.y - offset);
-                if !self.is_in_map(&p) || self.bridge_points.contains(&p) {
-                    has_up = false;
-                } else if self.islands_gate_pos.contains(&p) || offset == 1 {
-                    // continue;
-                } else if self.islands_pos.contains(&p) {
-                    result.insert(p);
-                    has_up = false
-                } else {
-                    result.insert(p);
-                }
-            }
-            if has_down {
-                let p = Vector2i::new(point.x, point.y + offset);
-                if !self.is_in_map(&p) || self.bridge_points.contains(&p) {
-                    has_down = false;
-                } else if self.islands_gate_pos.contains(&p) || offset == 1 {
-                    // continue;
-                } else if self.islands_pos.contains(&p) {
-                    result.insert(p);
-                    has_down = false
-                } else {
-                    result.insert(p);
-                }
+            if next == to || next == from || !self.shape().is_in_map(&next) {
+                break;
             }
-            if has_left {
-                let p = Vector2i::new(point.x - offset, point.y);
-                if !self.is_in_map(&p) || self.bridge_points.contains(&p) {
-                    has_left = false;
-                } else if self.islands_gate_pos.contains(&p) || offset == 1 {
-                    // continue;
-                } else if self.islands_pos.contains(&p) {
-                    result.insert(p);
-                    has_left = false;
-                } else {
-                    result.insert(p);
-                }
-            }
-            if has_right {
-                let p = Vector2i::new(point.x + offset, point.y);
-                if !self.is_in_map(&p) || self.bridge_points.contains(&p) {
-                    has_right = false;
-                } else if self.islands_gate_pos.contains(&p) || offset == 1 {
-                    // continue;
-                } else if self.islands_pos.contains(&p) {
-                    result.insert(p);
-                    has_right = false;
-                } else {
-                    result.insert(p);
-                }
-            }
-            result.limit_rng = result.value.len();
-            offset += 1;
+            p = next;
         }
-    }
-    fn is_in_map(&self, point: &Vector2i) -> bool {
-        if point.x < 0 || point.y < 0 {
-            return false;
-        }
-        if point.x >= self.get_width() || point.y >= self.get_height() {
-            return false;
+        res.push((start, self.grid_to_pixel(to) - trim));
+        res.retain(|(a, b)| (*b - *a).dot(trim) > 0.0);
+        if res.is_empty() {
+            res.push((
+                self.grid_to_pixel(from) + trim,
+                self.grid_to_pixel(to) - trim,
+            ));
         }
-        true
+        res
     }
-    fn fill_conditions(&mut self, from: Option<Vector2i>, current: Vector2i) {
-        if !self.islands_pos.contains(&current) {
-            self.islands_pos.push(current);
-        }
-        if !self.able_to_gen_islands.contains(&current) {
-            self.able_to_gen_islands.push(current);
-        }
-        for bridge_point in Self::calc_points(from, current, true).iter() {
-            if !self.bridge_points.contains(bridge_point) {
-                self.bridge_points.push(bridge_point.clone());
-            }
-        }
-        for gate in self.calc_island_gate_pos(current).iter() {
-            if !self.islands_gate_pos.contains(gate) {
-                self.islands_gate_pos.push(gate.clone());
-            }
+}
+
+/// 预览阶段
+impl GameMap {
+    /// 格子中心的像素坐标
+    fn grid_to_pixel(&self, pos: Vector2i) -> Vector2 {
+        self.variant.cell_center(pos, self.item_scale)
+    }
+    /// 根据拖动位置(相对岛屿左上角)计算方向，仍在岛屿范围内时为None
+    fn resolve_direction(&self, rel_pos: Vector2) -> Option<Direction2D> {
+        let half = self.item_scale / 2.0;
+        let v = rel_pos - Vector2::new(half, half);
+        if v.x.abs() <= half && v.y.abs() <= half {
+            return None;
         }
-        if self.islands_pos.len() >= self.get_max_bridge_count() as usize {
-            self.set_is_ready(true);
+        // 选择和拖动方向最接近的方向
+        self.variant.directions().iter().copied().max_by(|a, b| {
+            let da = v.dot(self.variant.pixel_direction(*a));
+            let db = v.dot(self.variant.pixel_direction(*b));
+            da.total_cmp(&db)
+        })
+    }
+    /// 返回目标岛屿以及这一步是否会改变桥梁
+    fn check_move(
+        &self,
+        src_pos: Vector2i,
+        direction: Direction2D,
+        is_sub: bool,
+    ) -> (Option<Vector2i>, bool) {
+        let Some(target_pos) = self.find_link_target(src_pos, direction) else {
+            return (None, false);
+        };
+        let src_has_bridge = self.get_bridge_count(src_pos, direction);
+        let legal = if is_sub {
+            src_has_bridge > 0
+        } else {
+            src_has_bridge > 0 || self.can_add_bridge(src_pos, target_pos, 1)
+        };
+        (Some(target_pos), legal)
+    }
+}
+
+impl BridgeRules for GameMap {
+    fn shape(&self) -> Shape<'_> {
+        Shape {
+            width: self.width,
+            height: self.height,
+            variant: self.variant,
+            wrap: self.wrap,
+            mask: self.mask.as_ref(),
         }
     }
+    fn islands_pos(&self) -> &[Vector2i] {
+        &self.islands_pos
+    }
+    fn island(&self, pos: Vector2i) -> Option<IslandState> {
+        let island = self.islands.get(pos)?.to::<Gd<Island>>();
+        Some(IslandState {
+            count: island.get(MAX_BRIDGE_COUNT.into()).to::<i32>(),
+            color_id: island.get(COLOR_ID.into()).to::<i32>(),
+            hidden: island.get(CLUE_HIDDEN.into()).to::<bool>(),
+            current: island.get(CURRENT_BRIDGE_COUNT.into()).to::<i32>(),
+        })
+    }
+    fn bridge_count(&self, pos: Vector2i, direction: Direction2D) -> i32 {
+        self.get_bridge_count(pos, direction)
+    }
+    fn is_obstacle(&self, pos: Vector2i) -> bool {
+        self.obstacles.contains(&pos)
+    }
+    fn user_bridges(&self) -> (&HashSet<Vector2i>, &HashSet<Vector2i>) {
+        (&self.user_bridge_points, &self.user_bridge_crossings)
+    }
+    fn user_bridges_mut(&mut self) -> (&mut HashSet<Vector2i>, &mut HashSet<Vector2i>) {
+        (
+            &mut self.user_bridge_points,
+            &mut self.user_bridge_crossings,
+        )
+    }
+    fn max_bridges_per_link(&self) -> i32 {
+        self.max_bridges_per_link
+    }
+    fn is_editor(&self) -> bool {
+        self.editor_mode
+    }
+}
+
+/// 两个方向的桥梁使用同一个key：(第一个岛屿, 从第一个岛屿出发的方向)
+fn link_key(
+    src_pos: Vector2i,
+    target_pos: Vector2i,
+    direction: Direction2D,
+) -> (Vector2i, Direction2D) {
+    let (first, _) = order_vector2i(src_pos, target_pos);
+    if first == src_pos {
+        (src_pos, direction)
+    } else {
+        (target_pos, direction.opposite())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::Rng;
 
     #[test]
     fn test_i32tuple_eq() {
@@ -614,21 +1570,49 @@ mod tests {
         assert_eq!(1, (2 as f64 * 0.99) as i32)
     }
     #[test]
-    fn test_calc_points() {
+    fn test_direction_between() {
         assert_eq!(
-            GameMap::calc_points(Some(Vector2i::new(0, 0)), Vector2i::new(0, 2), false),
-            vec![
-                Vector2i::new(0, 0),
-                Vector2i::new(0, 1),
-                Vector2i::new(0, 2)
-            ]
+            Direction2D::between(Vector2i::new(1, 1), Vector2i::new(1, 4)),
+            Some(Direction2D::Down)
+        );
+        assert_eq!(
+            Direction2D::between(Vector2i::new(1, 3), Vector2i::new(3, 1)),
+            Some(Direction2D::UpRight)
         );
         assert_eq!(
-            GameMap::calc_points(Some(Vector2i::new(0, 0)), Vector2i::new(0, 2), true),
-            vec![Vector2i::new(0, 1),]
+            Direction2D::between(Vector2i::new(0, 0), Vector2i::new(1, 2)),
+            None
         );
     }
     #[test]
+    fn test_link_key() {
+        let p1 = Vector2i::new(3, 1);
+        let p2 = Vector2i::new(1, 1);
+        assert_eq!(
+            link_key(p1, p2, Direction2D::Left),
+            link_key(p2, p1, Direction2D::Right)
+        );
+        assert_eq!(
+            link_key(p1, p2, Direction2D::Left),
+            (p2, Direction2D::Right)
+        );
+    }
+    #[test]
+    fn test_cell_at() {
+        for variant in [MapVariant::Square, MapVariant::Hex] {
+            for cell in [
+                Vector2i::new(0, 0),
+                Vector2i::new(3, 2),
+                Vector2i::new(5, 7),
+            ] {
+                let center = variant.cell_center(cell, 30.0);
+                assert_eq!(variant.cell_at(center, 30.0), cell);
+                let near = center + Vector2::new(10.0, -8.0);
+                assert_eq!(variant.cell_at(near, 30.0), cell);
+            }
+        }
+    }
+    #[test]
     fn test_range() {
         let r = 0..10;
         let mut count = 0;
@@ -0,0 +1,212 @@
+use super::game_map::{Direction2D, GameMap};
+use godot::prelude::*;
+
+/// 每个岛屿的基础分
+const SCORE_PER_ISLAND: i32 = 100;
+/// 每秒扣分
+const SCORE_PER_SECOND: f64 = 2.0;
+/// 限时模式中每剩余一秒的奖励分
+const SCORE_PER_REMAINING_SECOND: f64 = 5.0;
+const UNDO_PENALTY: i32 = 20;
+const HINT_PENALTY: i32 = 100;
+const MISTAKE_PENALTY: i32 = 50;
+
+/// 一局游戏，记录用时、操作次数等，完成时计算分数
+#[derive(GodotClass, Debug)]
+#[class(init, base = Node)]
+pub struct GameSession {
+    /// 本局的地图，只在`start`中读取，信号处理中不能再借用地图
+    #[export]
+    map: Option<Gd<GameMap>>,
+    /// 地图中岛屿的数量，`start`时记录
+    #[init(default = 0)]
+    island_count: i32,
+    /// 限时模式的时间限制(秒)，0表示不限时
+    #[init(default = 0.0)]
+    #[export]
+    time_limit: f64,
+    /// 已用时间(秒)，场景树暂停时不计时
+    #[init(default = 0.0)]
+    #[export]
+    elapsed: f64,
+    #[init(default = 0)]
+    #[export]
+    moves: i32,
+    #[init(default = 0)]
+    #[export]
+    undos: i32,
+    #[init(default = 0)]
+    #[export]
+    hints: i32,
+    /// 超出答案的搭桥次数
+    #[init(default = 0)]
+    #[export]
+    mistakes: i32,
+    #[init(default = 0)]
+    #[export]
+    score: i32,
+    /// 已经完成或者超时
+    #[init(default = false)]
+    #[export]
+    is_finished: bool,
+    base: Base<Node>,
+}
+
+#[godot_api]
+impl GameSession {
+    /// 完成谜题，score为最终分数
+    #[signal]
+    pub fn session_finished(score: i32) {}
+    /// 限时模式超时
+    #[signal]
+    pub fn time_up() {}
+    /// 地图生成之后开始计时，清空之前的记录
+    #[func]
+    fn start(&mut self) {
+        self.island_count = match &self.map {
+            Some(map) => map.bind().islands.len() as i32,
+            None => 0,
+        };
+        self.elapsed = 0.0;
+        self.moves = 0;
+        self.undos = 0;
+        self.hints = 0;
+        self.mistakes = 0;
+        self.score = 0;
+        self.is_finished = false;
+    }
+    /// 暂停整个场景树，计时也会停止
+    #[func]
+    fn pause(&mut self) {
+        self.set_tree_paused(true);
+    }
+    #[func]
+    fn resume(&mut self) {
+        self.set_tree_paused(false);
+    }
+    #[func]
+    fn is_paused(&self) -> bool {
+        match self.base().get_tree() {
+            Some(tree) => tree.is_paused(),
+            None => false,
+        }
+    }
+    /// 限时模式的剩余时间，不限时为-1
+    #[func]
+    fn remaining_time(&self) -> f64 {
+        if self.time_limit <= 0.0 {
+            return -1.0;
+        }
+        (self.time_limit - self.elapsed).max(0.0)
+    }
+    #[func]
+    fn on_user_moved(
+        &mut self,
+        _src_pos: Vector2i,
+        _direction: Direction2D,
+        _count: i32,
+        mistake: bool,
+    ) {
+        if self.is_finished {
+            return;
+        }
+        self.moves += 1;
+        if mistake {
+            self.mistakes += 1;
+        }
+    }
+    #[func]
+    fn on_move_undone(&mut self) {
+        if !self.is_finished {
+            self.undos += 1;
+        }
+    }
+    #[func]
+    fn on_hint_used(&mut self) {
+        if !self.is_finished {
+            self.hints += 1;
+        }
+    }
+    #[func]
+    fn on_puzzle_solved(&mut self) {
+        if self.is_finished {
+            return;
+        }
+        self.score = Self::calc_score(
+            self.island_count,
+            self.elapsed,
+            self.time_limit,
+            self.undos,
+            self.hints,
+            self.mistakes,
+        );
+        self.is_finished = true;
+        let score = self.score;
+        self.base_mut()
+            .emit_signal("session_finished".into(), &[Variant::from(score)]);
+    }
+    fn set_tree_paused(&mut self, paused: bool) {
+        if let Some(mut tree) = self.base().get_tree() {
+            tree.set_pause(paused);
+        }
+    }
+    /// 分数：岛屿基础分减去用时、撤销、提示和错误的扣分，限时模式加上剩余时间的奖励，最低为0
+    pub fn calc_score(
+        island_count: i32,
+        elapsed: f64,
+        time_limit: f64,
+        undos: i32,
+        hints: i32,
+        mistakes: i32,
+    ) -> i32 {
+        let mut score = (island_count * SCORE_PER_ISLAND) as f64 - elapsed * SCORE_PER_SECOND;
+        if time_limit > 0.0 {
+            score += (time_limit - elapsed).max(0.0) * SCORE_PER_REMAINING_SECOND;
+        }
+        score -= (undos * UNDO_PENALTY + hints * HINT_PENALTY + mistakes * MISTAKE_PENALTY) as f64;
+        score.max(0.0) as i32
+    }
+}
+
+#[godot_api]
+impl INode for GameSession {
+    fn ready(&mut self) {
+        let Some(mut map) = self.map.clone() else {
+            godot_error!("GameSession没有设置map");
+            return;
+        };
+        let this = self.base().clone();
+        for (signal, method) in [
+            ("user_moved", "on_user_moved"),
+            ("move_undone", "on_move_undone"),
+            ("hint_used", "on_hint_used"),
+            ("puzzle_solved", "on_puzzle_solved"),
+        ] {
+            map.connect(signal.into(), Callable::from_object_method(&this, method));
+        }
+    }
+    fn process(&mut self, delta: f64) {
+        if self.is_finished {
+            return;
+        }
+        self.elapsed += delta;
+        if self.time_limit > 0.0 && self.elapsed >= self.time_limit {
+            self.elapsed = self.time_limit;
+            self.is_finished = true;
+            self.base_mut().emit_signal("time_up".into(), &[]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calc_score() {
+        assert_eq!(GameSession::calc_score(10, 100.0, 0.0, 1, 1, 1), 630);
+        // 限时模式剩余时间有奖励
+        assert_eq!(GameSession::calc_score(10, 100.0, 160.0, 0, 0, 0), 1100);
+        assert_eq!(GameSession::calc_score(1, 1000.0, 0.0, 0, 5, 0), 0);
+    }
+}
@@ -0,0 +1,419 @@
+use super::game_map::Direction2D;
+use super::puzzle::{Puzzle, PuzzleIsland};
+use super::shape::Shape;
+use super::solver::Solver;
+use godot::prelude::*;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug)]
+struct RandInsertVec<T> {
+    pub value: Vec<T>,
+    pub limit_rng: usize,
+}
+impl<T> RandInsertVec<T> {
+    fn new() -> Self {
+        RandInsertVec {
+            value: vec![],
+            limit_rng: 0,
+        }
+    }
+    /// 使用地图的rng，保证相同的种子生成相同的地图
+    fn insert(&mut self, p: T, rng: &mut ChaCha8Rng) {
+        if self.limit_rng < self.value.len() {
+            self.value.push(p);
+            return;
+        }
+        //随机插入
+        let index = rng.gen_range(self.limit_rng..=self.value.len());
+        self.value.insert(index, p);
+        return;
+    }
+}
+
+/// 生成器的岛屿
+#[derive(Debug, Clone, Copy)]
+struct GenIsland {
+    count: i32,
+    color_id: i32,
+}
+
+/// 随机生成谜题：先放置障碍物和每种颜色的第一个岛屿，之后不断从已有的岛屿出发，
+/// 搭桥连接新的岛屿或者相同颜色的已有岛屿，直到岛屿数量达到上限或者无法继续。
+/// 不需要引擎，`GameMap::generate`生成之后加载得到的谜题
+pub struct Generator<'a> {
+    shape: Shape<'a>,
+    game_mode: i32,
+    color_count: i32,
+    max_bridges_per_link: i32,
+    /// 岛屿数量的上限，见`max_island_count`
+    max_island_count: i32,
+    /// 地图的rng，相同的种子和参数生成相同的谜题
+    rng: &'a mut ChaCha8Rng,
+    obstacles: HashSet<Vector2i>,
+    islands: HashMap<Vector2i, GenIsland>,
+    islands_pos: Vec<Vector2i>,
+    /// 岛屿周围的格子，不能放置新的岛屿
+    islands_gate_pos: HashSet<Vector2i>,
+    able_to_gen_islands: Vec<Vector2i>,
+    bridge_points: HashSet<Vector2i>,
+    /// 对角线桥梁的交点，见`Shape::calc_crossings`
+    bridge_crossings: HashSet<Vector2i>,
+    finished: bool,
+}
+
+impl<'a> Generator<'a> {
+    pub fn new(
+        shape: Shape<'a>,
+        game_mode: i32,
+        color_count: i32,
+        max_bridges_per_link: i32,
+        rng: &'a mut ChaCha8Rng,
+    ) -> Self {
+        Generator {
+            shape,
+            game_mode,
+            color_count: color_count.max(1),
+            max_bridges_per_link,
+            max_island_count: Self::max_island_count(game_mode, shape.area()),
+            rng,
+            obstacles: HashSet::new(),
+            islands: HashMap::new(),
+            islands_pos: vec![],
+            islands_gate_pos: HashSet::new(),
+            able_to_gen_islands: vec![],
+            bridge_points: HashSet::new(),
+            bridge_crossings: HashSet::new(),
+            finished: false,
+        }
+    }
+    /// 岛屿数量的上限
+    pub fn max_island_count(game_mode: i32, area: i32) -> i32 {
+        let n = {
+            if game_mode > 2 {
+                0.25
+            } else {
+                0.18
+            }
+        };
+        (area as f32 * n) as i32
+    }
+    /// 生成唯一解的谜题，不是唯一解(或者太复杂无法判断)时继续生成，最多尝试attempts次，
+    /// 都不是唯一解时返回最后一次生成的谜题
+    pub fn generate_unique(&mut self, attempts: usize) -> Result<Puzzle, String> {
+        let mut res = Err("没有尝试生成".to_string());
+        for _ in 0..attempts.max(1) {
+            res = match self.generate() {
+                Ok(puzzle) if Solver::count_solutions(&puzzle, 2) == Some(1) => {
+                    return Ok(puzzle);
+                }
+                // 之前生成成功的谜题比这次的失败好
+                Err(_) if res.is_ok() => continue,
+                other => other,
+            };
+        }
+        res
+    }
+    /// 生成一个谜题，失败时返回生成结束时的信息
+    pub fn generate(&mut self) -> Result<Puzzle, String> {
+        self.islands.clear();
+        self.islands_pos.clear();
+        self.islands_gate_pos.clear();
+        self.able_to_gen_islands.clear();
+        self.bridge_points.clear();
+        self.bridge_crossings.clear();
+        self.finished = false;
+        self.place_obstacles();
+        while !self.finished {
+            self.gen_island()?;
+        }
+        Ok(self.to_puzzle())
+    }
+    fn to_puzzle(&self) -> Puzzle {
+        let mut obstacles: Vec<Vector2i> = self.obstacles.iter().copied().collect();
+        obstacles.sort_by_key(|p| (p.y, p.x));
+        Puzzle {
+            width: self.shape.width,
+            height: self.shape.height,
+            variant: self.shape.variant,
+            wrap: self.shape.wrap,
+            max_bridges_per_link: self.max_bridges_per_link,
+            islands: self
+                .islands_pos
+                .iter()
+                .map(|p| PuzzleIsland {
+                    pos: *p,
+                    count: self.islands[p].count,
+                    color_id: self.islands[p].color_id,
+                    hidden: false,
+                })
+                .collect(),
+            obstacles,
+            holes: self.shape.holes(),
+        }
+    }
+    /// 生成一个岛屿，没有可以生成的位置时结束生成
+    fn gen_island(&mut self) -> Result<(), String> {
+        if self.islands_pos.len() < self.color_count as usize {
+            // 初始化每种颜色的第一个节点
+            let candidates: Vec<Vector2i> = self
+                .shape
+                .rect_cells()
+                .into_iter()
+                .filter(|p| {
+                    self.shape.is_in_bounds(p)
+                        && !self.obstacles.contains(p)
+                        && !self.islands.contains_key(p)
+                        && !self.islands_gate_pos.contains(p)
+                        && !self.bridge_points.contains(p)
+                })
+                .collect();
+            if candidates.is_empty() {
+                self.finished = true;
+                return Ok(());
+            }
+            let first_point = candidates[self.rng.gen_range(0..candidates.len())];
+            let color_id = self.islands_pos.len() as i32;
+            self.link_island(None, first_point, color_id);
+            return Ok(());
+        }
+        let (mut src_position, mut index) =
+            self.select_random_island().ok_or("已经没有可生成节点")?;
+        let mut valid_next_points = self.calc_valid_next_point(src_position);
+        while valid_next_points.is_empty() && !self.able_to_gen_islands.is_empty() {
+            self.able_to_gen_islands.remove(index);
+            (src_position, index) = self.select_random_island().ok_or("已经没有可生成节点")?;
+            valid_next_points = self.calc_valid_next_point(src_position);
+        }
+        if valid_next_points.is_empty() {
+            self.finished = true;
+            return Ok(());
+        }
+        let rindex = {
+            let gen_per = self.islands_pos.len() as f32 / self.max_island_count as f32;
+            if gen_per < 0.95 {
+                let b = self.rng.gen_bool(0.05 * self.game_mode as f64);
+                self.weighted_random_index(valid_next_points.len(), b)
+            } else {
+                self.rng.gen_range(0..valid_next_points.len())
+            }
+        };
+        let (next_point, direction) = valid_next_points[rindex];
+        let color_id = self.islands[&src_position].color_id;
+        self.link_island(Some((src_position, direction)), next_point, color_id);
+        Ok(())
+    }
+    /// 按照游戏模式随机放置障碍物
+    fn place_obstacles(&mut self) {
+        self.obstacles.clear();
+        let area = self.shape.area();
+        let n = (area as f32 * Self::calc_obstacle_density(self.game_mode)) as usize;
+        while self.obstacles.len() < n {
+            let p = Vector2i::new(
+                self.rng.gen_range(0..self.shape.width),
+                self.rng.gen_range(0..self.shape.height),
+            );
+            if self.shape.is_in_bounds(&p) {
+                self.obstacles.insert(p);
+            }
+        }
+    }
+    /// 障碍物占地图的比例，简单模式没有障碍物
+    fn calc_obstacle_density(game_mode: i32) -> f32 {
+        (0.02 * (game_mode - 1) as f32).clamp(0.0, 0.1)
+    }
+    fn weighted_random_index(&mut self, n: usize, more_weight: bool) -> usize {
+        let weights: Vec<f32>;
+        if more_weight {
+            // 指数权重
+            weights = (0..n).map(|i| (-(i as f32)).exp()).collect();
+        } else {
+            // 线性权重
+            weights = (0..n).map(|i| 1.0 / (i as f32 * 0.8 + 1.0)).collect();
+        }
+
+        let total_weight = weights.iter().sum::<f32>();
+        let rand_v = self.rng.gen_range(0.0..total_weight);
+        let mut cumulative_weight = 0.0;
+        for (i, &weight) in weights.iter().enumerate() {
+            cumulative_weight += weight;
+            if rand_v < cumulative_weight {
+                return i;
+            }
+        }
+        n
+    }
+    fn select_random_island(&mut self) -> Option<(Vector2i, usize)> {
+        if self.able_to_gen_islands.is_empty() {
+            return None;
+        }
+        let index = self.rng.gen_range(0..self.able_to_gen_islands.len());
+        let island = self.able_to_gen_islands.get(index).unwrap();
+        Some((island.clone(), index))
+    }
+    /// 连接到current_pos的岛屿，没有时新建color_id颜色的岛屿
+    fn link_island(
+        &mut self,
+        from: Option<(Vector2i, Direction2D)>,
+        current_pos: Vector2i,
+        color_id: i32,
+    ) {
+        self.islands
+            .entry(current_pos)
+            .or_insert(GenIsland { count: 0, color_id });
+        self.fill_conditions(from, current_pos);
+        if let Some((from_pos, _)) = from {
+            // 每多一座桥的概率
+            let more_p = (0.55 - 0.02 * self.game_mode as f64).clamp(0.0, 1.0);
+            let mut bridge_count = 1;
+            while bridge_count < self.max_bridges_per_link && self.rng.gen_bool(more_p) {
+                bridge_count += 1;
+            }
+            self.islands.get_mut(&from_pos).unwrap().count += bridge_count;
+            self.islands.get_mut(&current_pos).unwrap().count += bridge_count;
+        }
+    }
+    /// 从一个岛屿出发，可以生成的另一个岛屿以及方向
+    fn calc_valid_next_point(&mut self, point: Vector2i) -> Vec<(Vector2i, Direction2D)> {
+        let mut result = RandInsertVec::new();
+        let color_id = self.islands[&point].color_id;
+        let directions = self.shape.variant.directions();
+        let mut has_next = vec![true; directions.len()];
+        let mut cursors = vec![point; directions.len()];
+        let mut offset = 1;
+        loop {
+            if !has_next.iter().any(|v| *v) {
+                return result.value;
+            }
+            for (i, direction) in directions.iter().enumerate() {
+                if !has_next[i] {
+                    continue;
+                }
+                let crossing = self.shape.crossing_key(cursors[i], *direction);
+                let p = match self.shape.next_pos(cursors[i], *direction) {
+                    Some(p) if p != point => p,
+                    _ => {
+                        has_next[i] = false;
+                        continue;
+                    }
+                };
+                cursors[i] = p;
+                if self.bridge_points.contains(&p)
+                    || self.bridge_crossings.contains(&crossing)
+                    || self.obstacles.contains(&p)
+                {
+                    has_next[i] = false;
+                } else if self.islands_gate_pos.contains(&p) || offset == 1 {
+                    // continue;
+                } else if let Some(island) = self.islands.get(&p) {
+                    // 只能连接相同颜色的岛屿
+                    if island.color_id == color_id {
+                        result.insert((p, *direction), self.rng);
+                    }
+                    has_next[i] = false;
+                } else {
+                    result.insert((p, *direction), self.rng);
+                }
+            }
+            result.limit_rng = result.value.len();
+            offset += 1;
+        }
+    }
+    fn fill_conditions(&mut self, from: Option<(Vector2i, Direction2D)>, current: Vector2i) {
+        if !self.islands_pos.contains(&current) {
+            self.islands_pos.push(current);
+        }
+        if !self.able_to_gen_islands.contains(&current) {
+            self.able_to_gen_islands.push(current);
+        }
+        if let Some((from, direction)) = from {
+            let (points, crossings) = self.shape.walk_link(from, current, direction);
+            self.bridge_points.extend(points);
+            self.bridge_crossings.extend(crossings);
+        }
+        self.islands_gate_pos.extend(self.shape.gate_pos(current));
+        if self.islands_pos.len() >= self.max_island_count as usize {
+            self.finished = true;
+        }
+    }
+}
+
+/// 按照ratio隐藏岛屿的数量，每隐藏一个都要保持唯一解，返回隐藏的数量。
+/// 谜题本身不是唯一解时不隐藏，所以要先用`Generator::generate_unique`生成
+pub fn hide_clues(puzzle: &mut Puzzle, ratio: f32, rng: &mut ChaCha8Rng) -> usize {
+    let target = (puzzle.islands.len() as f32 * ratio) as usize;
+    if target == 0 {
+        return 0;
+    }
+    if Solver::count_solutions(puzzle, 2) != Some(1) {
+        // 本身不是唯一解，隐藏之后也不会是
+        return 0;
+    }
+    let mut order: Vec<usize> = (0..puzzle.islands.len()).collect();
+    order.shuffle(rng);
+    let mut hidden = 0;
+    for i in order {
+        if hidden >= target {
+            break;
+        }
+        puzzle.islands[i].hidden = true;
+        if Solver::count_solutions(puzzle, 2) != Some(1) {
+            puzzle.islands[i].hidden = false;
+            continue;
+        }
+        hidden += 1;
+    }
+    hidden
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::game_map::MapVariant;
+    use super::*;
+    use rand::SeedableRng;
+
+    fn rect(width: i32, height: i32) -> Shape<'static> {
+        Shape {
+            width,
+            height,
+            variant: MapVariant::Square,
+            wrap: false,
+            mask: None,
+        }
+    }
+
+    #[test]
+    fn test_same_seed() {
+        let gen = |seed| {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            Generator::new(rect(8, 6), 1, 1, 2, &mut rng).generate_unique(20)
+        };
+        assert_eq!(gen(7), gen(7));
+        assert_ne!(gen(7), gen(8));
+    }
+    #[test]
+    fn test_generate_unique() {
+        for seed in 0..10 {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let puzzle = Generator::new(rect(7, 7), 1, 1, 2, &mut rng)
+                .generate_unique(20)
+                .unwrap();
+            assert_eq!(Solver::count_solutions(&puzzle, 2), Some(1), "种子{}", seed);
+        }
+    }
+    #[test]
+    fn test_hide_clues() {
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let mut puzzle = Generator::new(rect(7, 7), 1, 1, 2, &mut rng)
+            .generate_unique(20)
+            .unwrap();
+        let hidden = hide_clues(&mut puzzle, 0.3, &mut rng);
+        assert!(hidden > 0);
+        assert_eq!(puzzle.islands.iter().filter(|i| i.hidden).count(), hidden);
+        assert_eq!(Solver::count_solutions(&puzzle, 2), Some(1));
+        // 比例为0时不隐藏
+        assert_eq!(hide_clues(&mut puzzle, 0.0, &mut rng), 0);
+    }
+}
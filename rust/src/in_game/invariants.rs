@@ -0,0 +1,165 @@
+use super::game_map::Direction2D;
+use super::puzzle::Puzzle;
+use super::solver::Solver;
+use godot::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+/// 生成的地图应该满足的性质，返回第一个不满足的性质：
+///
+/// - 岛屿都在`width × height`范围内
+/// - 岛屿之间不相邻，见`Generator::islands_gate_pos`
+/// - 岛屿需要的桥梁数量在1和每个方向都搭满之间，标准地图为1到8
+/// - 有解，并且解中的桥梁不交叉
+pub fn check_generated(puzzle: &Puzzle) -> Result<(), String> {
+    check_bounds(puzzle)?;
+    check_not_adjacent(puzzle)?;
+    check_clues(puzzle)?;
+    let solution = Solver::solve(puzzle).ok_or("无解或者太复杂")?;
+    check_no_crossing(puzzle, &solution)
+}
+
+fn check_bounds(puzzle: &Puzzle) -> Result<(), String> {
+    for island in puzzle.islands.iter() {
+        if !in_rect(puzzle, island.pos) {
+            return Err(format!("岛屿{:?}超出地图范围", island.pos));
+        }
+    }
+    Ok(())
+}
+
+fn check_not_adjacent(puzzle: &Puzzle) -> Result<(), String> {
+    let positions: HashSet<Vector2i> = puzzle.islands.iter().map(|i| i.pos).collect();
+    for island in puzzle.islands.iter() {
+        for direction in puzzle.variant.directions() {
+            let Some(p) = next_pos(puzzle, island.pos, *direction) else {
+                continue;
+            };
+            if p != island.pos && positions.contains(&p) {
+                return Err(format!("岛屿{:?}和{:?}相邻", island.pos, p));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_clues(puzzle: &Puzzle) -> Result<(), String> {
+    let max = puzzle.max_bridges_per_link * puzzle.variant.directions().len() as i32;
+    for island in puzzle.islands.iter() {
+        if island.count < 1 || island.count > max {
+            return Err(format!(
+                "岛屿{:?}需要的桥梁数量{}不在1到{}之间",
+                island.pos, island.count, max
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// 解中有桥的连线经过的格子和对角线交点都不能重复，和`Solver`分开计算
+fn check_no_crossing(
+    puzzle: &Puzzle,
+    solution: &[(Vector2i, Direction2D, i32)],
+) -> Result<(), String> {
+    let positions: HashSet<Vector2i> = puzzle.islands.iter().map(|i| i.pos).collect();
+    let (w, h) = (puzzle.width, puzzle.height);
+    let mut cells: HashMap<Vector2i, Vector2i> = HashMap::new();
+    let mut crossings: HashMap<Vector2i, Vector2i> = HashMap::new();
+    for (pos, direction, count) in solution.iter() {
+        if *count <= 0 {
+            continue;
+        }
+        let step = direction.to_vector();
+        let mut p = *pos;
+        loop {
+            if step.x != 0 && step.y != 0 {
+                let mut key = p * 2 + step;
+                if puzzle.wrap {
+                    key = Vector2i::new(key.x.rem_euclid(w * 2), key.y.rem_euclid(h * 2));
+                }
+                if let Some(other) = crossings.insert(key, *pos) {
+                    return Err(format!("从{:?}和{:?}出发的桥梁交叉", other, pos));
+                }
+            }
+            p = match next_pos(puzzle, p, *direction) {
+                Some(next) if next != *pos => next,
+                _ => return Err(format!("从{:?}出发的桥梁没有连到岛屿", pos)),
+            };
+            if positions.contains(&p) {
+                break;
+            }
+            if let Some(other) = cells.insert(p, *pos) {
+                return Err(format!("从{:?}和{:?}出发的桥梁交叉", other, pos));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn in_rect(puzzle: &Puzzle, p: Vector2i) -> bool {
+    p.x >= 0 && p.y >= 0 && p.x < puzzle.width && p.y < puzzle.height
+}
+
+/// 沿方向前进一格，见`Shape::next_pos`
+fn next_pos(puzzle: &Puzzle, p: Vector2i, direction: Direction2D) -> Option<Vector2i> {
+    let mut next = p + direction.to_vector();
+    if puzzle.wrap {
+        next = Vector2i::new(
+            next.x.rem_euclid(puzzle.width),
+            next.y.rem_euclid(puzzle.height),
+        );
+    }
+    if in_rect(puzzle, next) && !puzzle.holes.contains(&next) {
+        Some(next)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::in_game::puzzle::PuzzleIsland;
+
+    fn puzzle(text: &str) -> Puzzle {
+        Puzzle::from_text(text).unwrap()
+    }
+
+    #[test]
+    fn test_check_generated() {
+        assert_eq!(
+            check_generated(&puzzle("size 3 3\ngrid\n2 . 3\n. . .\n. . 1\n")),
+            Ok(())
+        );
+        // 相邻
+        assert!(check_generated(&puzzle("size 3 1\ngrid\n1 1 .\n")).is_err());
+        // 环形地图中首尾相邻
+        assert!(check_generated(&puzzle("size 3 1\nwrap 1\ngrid\n1 . 1\n")).is_err());
+        // 数量超出范围
+        assert!(check_generated(&puzzle("size 3 1\ngrid\n9 . 1\n")).is_err());
+        assert!(check_generated(&puzzle("size 3 1\ngrid\n0 . 0\n")).is_err());
+        // 无解
+        assert!(check_generated(&puzzle("size 3 1\ngrid\n2 . 1\n")).is_err());
+        let mut p = puzzle("size 3 1\ngrid\n1 . 1\n");
+        p.islands.push(PuzzleIsland {
+            pos: Vector2i::new(5, 0),
+            count: 1,
+            color_id: 0,
+            hidden: false,
+        });
+        assert!(check_bounds(&p).is_err());
+    }
+    #[test]
+    fn test_check_no_crossing() {
+        let p = puzzle("size 3 3\ngrid\n. 1 .\n1 . 1\n. 1 .\n");
+        let across = (Vector2i::new(0, 1), Direction2D::Right, 1);
+        let down = (Vector2i::new(1, 0), Direction2D::Down, 1);
+        assert_eq!(check_no_crossing(&p, &[across]), Ok(()));
+        assert!(check_no_crossing(&p, &[across, down]).is_err());
+        assert!(check_no_crossing(&p, &[(Vector2i::new(0, 1), Direction2D::Up, 1)]).is_err());
+        // 对角线在格子之间交叉
+        let p = puzzle("size 2 2\nvariant Diagonal\ngrid\n1 1\n1 1\n");
+        let a = (Vector2i::new(0, 0), Direction2D::DownRight, 1);
+        let b = (Vector2i::new(1, 0), Direction2D::DownLeft, 1);
+        assert!(check_no_crossing(&p, &[a, b]).is_err());
+    }
+}
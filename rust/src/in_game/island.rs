@@ -1,8 +1,18 @@
 use godot::engine::{
     global::MouseButton, Button, IButton, InputEvent, InputEventMouseButton, InputEventMouseMotion,
+    InputEventScreenDrag, InputEventScreenTouch, Time,
 };
 use godot::prelude::*;
 
+use super::game_map::Direction2D;
+
+/// 由触摸模拟出来的鼠标事件的设备id，已经单独处理过触摸事件，需要忽略
+const DEVICE_ID_EMULATION: i32 = -1;
+/// 按住岛屿超过这个时间(毫秒)之后再拖动为拆桥，对应鼠标的右键拖动
+const LONG_PRESS_MS: u64 = 500;
+/// 手指移动超过这个距离才算开始拖动，忽略按住时的抖动
+const DRAG_THRESHOLD: f32 = 8.0;
+
 /// 定义岛屿
 #[derive(GodotClass, Debug)]
 #[class(init, base=Button)]
@@ -13,6 +23,10 @@ pub struct Island {
     #[init(default = false)]
     #[export]
     pub is_drag: bool,
+    /// 当前拖动是否为拆桥：鼠标右键或者按住shift拖动，触摸时长按之后拖动
+    #[init(default = false)]
+    #[export]
+    pub is_sub_drag: bool,
     /// 最大桥梁数量
     #[init(default = 0)]
     #[export]
@@ -21,9 +35,29 @@ pub struct Island {
     #[init(default = 0)]
     #[export]
     pub current_bridge_count: i32,
-    #[init(default = Vector4i::new(0, 0, 0, 0))]
+    /// 每个方向的桥梁数量，下标见`Direction2D::index`
+    #[init(default = PackedInt32Array::from(&[0; Direction2D::COUNT][..]))]
+    #[export]
+    pub bridge_states: PackedInt32Array,
+    /// 颜色编号，只有相同颜色的岛屿之间可以搭桥
+    #[init(default = 0)]
     #[export]
-    pub bridge_state: Vector4i,
+    pub color_id: i32,
+    /// 是否隐藏最大桥梁数量(问号岛屿)
+    #[init(default = false)]
+    #[export]
+    pub clue_hidden: bool,
+    /// 正在拖动的手指，-1表示没有
+    #[init(default = -1)]
+    touch_index: i32,
+    /// 手指按下的时间和位置，用于判断长按
+    #[init(default = 0)]
+    touch_start_ms: u64,
+    #[init(default = Vector2::ZERO)]
+    touch_start_pos: Vector2,
+    /// 按下之后是否已经拖动过
+    #[init(default = false)]
+    touch_moved: bool,
     base: Base<Button>,
 }
 
@@ -34,9 +68,16 @@ impl Island {
         Gd::from_init_fn(|base| Self {
             pos,
             is_drag: false,
+            is_sub_drag: false,
             max_bridge_count: 0,
             current_bridge_count: 0,
-            bridge_state: Vector4i::new(0, 0, 0, 0),
+            bridge_states: PackedInt32Array::from(&[0; Direction2D::COUNT][..]),
+            color_id: 0,
+            clue_hidden: false,
+            touch_index: -1,
+            touch_start_ms: 0,
+            touch_start_pos: Vector2::ZERO,
+            touch_moved: false,
             base,
         })
     }
@@ -46,11 +87,64 @@ impl Island {
     pub fn preview_bridge(src: Gd<Island>, target_pos: Vector2) {}
     #[signal]
     pub fn change_bridge_count(src: Gd<Island>) {}
+    fn emit_drag_signal(&mut self, signal: &str, position: Vector2) {
+        let mut gd = self.base_mut();
+        let param = &[
+            Variant::from_variant(&gd.to_variant()),
+            Variant::from(position),
+        ];
+        gd.emit_signal(signal.into(), param);
+    }
+    /// 处理触摸事件，返回是否已处理
+    fn touch_input(&mut self, event: &Gd<InputEvent>) -> bool {
+        if event.is_class("InputEventScreenTouch".into()) {
+            let event = event.clone().cast::<InputEventScreenTouch>();
+            if event.is_pressed() {
+                if self.touch_index < 0 {
+                    self.touch_index = event.get_index();
+                    self.touch_start_ms = Time::singleton().get_ticks_msec();
+                    self.touch_start_pos = event.get_position();
+                    self.touch_moved = false;
+                    self.set_is_drag(true);
+                    self.set_is_sub_drag(false);
+                }
+                return true;
+            }
+            if event.get_index() != self.touch_index {
+                return true;
+            }
+            self.touch_index = -1;
+            self.set_is_drag(false);
+            if !event.is_canceled() {
+                self.emit_drag_signal("finish_preview_bridge", event.get_position());
+            }
+            return true;
+        }
+        if event.is_class("InputEventScreenDrag".into()) {
+            let event = event.clone().cast::<InputEventScreenDrag>();
+            if self.get_is_drag() && event.get_index() == self.touch_index {
+                // 长按之后开始拖动为拆桥
+                if !self.touch_moved
+                    && event.get_position().distance_to(self.touch_start_pos) >= DRAG_THRESHOLD
+                {
+                    self.touch_moved = true;
+                    let held = Time::singleton().get_ticks_msec() - self.touch_start_ms;
+                    self.set_is_sub_drag(held >= LONG_PRESS_MS);
+                }
+                self.emit_drag_signal("preview_bridge", event.get_position());
+            }
+            return true;
+        }
+        false
+    }
 }
 
 #[godot_api]
 impl IButton for Island {
     fn gui_input(&mut self, event: Gd<InputEvent>) {
+        if self.touch_input(&event) || event.get_device() == DEVICE_ID_EMULATION {
+            return;
+        }
         if event.is_class("InputEventMouseMotion".into()) && self.get_is_drag() {
             let event = event.cast::<InputEventMouseMotion>();
             // godot_print!(
@@ -58,32 +152,26 @@ impl IButton for Island {
             //     self.pos,
             //     event.get_position()
             // );
-            let mut gd = self.base_mut();
-            let param = &[
-                Variant::from_variant(&gd.to_variant()),
-                Variant::from(event.get_position()),
-            ];
-            gd.emit_signal("preview_bridge".into(), param);
+            self.emit_drag_signal("preview_bridge", event.get_position());
             return;
         }
         if !event.is_class("InputEventMouseButton".into()) {
             return;
         }
         let event = event.cast::<InputEventMouseButton>();
-        if event.get_button_index() == MouseButton::LEFT {
+        let btn_index = event.get_button_index();
+        if btn_index == MouseButton::LEFT || btn_index == MouseButton::RIGHT {
             if event.is_released() {
+                if !self.get_is_drag() {
+                    return;
+                }
                 self.set_is_drag(false);
                 // godot_print!(
                 //     "鼠标释放事件：岛屿：{:?}， 坐标：{:?}",
                 //     self.pos,
                 //     event.get_position()
                 // );
-                let mut gd = self.base_mut();
-                let param = &[
-                    Variant::from_variant(&gd.to_variant()),
-                    Variant::from(event.get_position()),
-                ];
-                gd.emit_signal("finish_preview_bridge".into(), param);
+                self.emit_drag_signal("finish_preview_bridge", event.get_position());
                 return;
             }
             if event.is_pressed() {
@@ -93,6 +181,8 @@ impl IButton for Island {
                 //     event.get_position()
                 // );
                 self.set_is_drag(true);
+                // 右键拖动或者按住shift拖动为拆桥
+                self.set_is_sub_drag(btn_index == MouseButton::RIGHT || event.is_shift_pressed());
                 return;
             }
         }
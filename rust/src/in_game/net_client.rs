@@ -0,0 +1,265 @@
+use super::game_map::{Direction2D, GameMap};
+use super::protocol::{sanitize_name, Message, DEFAULT_PORT};
+use godot::engine::global::Error;
+use godot::engine::stream_peer_tcp::Status;
+use godot::engine::StreamPeerTcp;
+use godot::prelude::*;
+
+/// 联网对战的客户端：通过中继服务器(见`bin/relay.rs`)和对手交换地图参数和操作
+///
+/// 没有自己的process，需要每帧调用`poll`，收到的消息通过信号通知，
+/// 可以在切换场景之后继续使用同一个连接
+#[derive(GodotClass, Debug)]
+#[class(init, base = RefCounted)]
+pub struct NetClient {
+    #[init(default = StreamPeerTcp::new_gd())]
+    peer: Gd<StreamPeerTcp>,
+    name: String,
+    /// 还没有收到换行符的数据
+    buffer: Vec<u8>,
+    /// 已经发送了join
+    joined: bool,
+    /// 中继分配的玩家编号，-1表示还没有分配
+    #[init(default = -1)]
+    player: i32,
+    opponent_name: String,
+    /// (种子, 宽, 高, 难度)
+    board: Option<(i64, i32, i32, i32)>,
+    /// 显示对手操作的地图，用于丢弃不是从岛屿出发的操作
+    opponent_map: Option<Gd<GameMap>>,
+    base: Base<RefCounted>,
+}
+
+#[godot_api]
+impl NetClient {
+    #[signal]
+    pub fn welcome(player: i32) {}
+    #[signal]
+    pub fn opponent_joined(name: GString) {}
+    #[signal]
+    pub fn board_received(seed: i64, width: i32, height: i32, game_mode: i32) {}
+    #[signal]
+    pub fn opponent_moved(src_pos: Vector2i, direction: Direction2D, count: i32) {}
+    #[signal]
+    pub fn opponent_finished(seconds: f64) {}
+    #[signal]
+    pub fn opponent_left() {}
+    /// 和中继服务器的连接断开或者连接失败
+    #[signal]
+    pub fn disconnected() {}
+    #[func]
+    fn default_port() -> i32 {
+        DEFAULT_PORT as i32
+    }
+    /// 连接中继服务器，连接成功之后自动加入，返回错误信息
+    #[func]
+    fn connect_to(&mut self, host: GString, port: i32, name: GString) -> GString {
+        self.close();
+        self.name = sanitize_name(&name.to_string());
+        let err = self.peer.connect_to_host(host.clone(), port);
+        if err != Error::OK {
+            return format!("无法连接{}:{}", host, port).into();
+        }
+        GString::new()
+    }
+    #[func]
+    fn close(&mut self) {
+        self.peer.disconnect_from_host();
+        self.buffer.clear();
+        self.joined = false;
+        self.player = -1;
+        self.opponent_name.clear();
+        self.board = None;
+        self.opponent_map = None;
+    }
+    /// 处理收到的消息，每帧调用
+    #[func]
+    fn poll(&mut self) {
+        if self.peer.get_status() == Status::NONE {
+            return;
+        }
+        self.peer.poll();
+        match self.peer.get_status() {
+            Status::CONNECTED => {}
+            Status::CONNECTING => return,
+            _ => {
+                self.close();
+                self.base_mut().emit_signal("disconnected".into(), &[]);
+                return;
+            }
+        }
+        if !self.joined {
+            self.joined = true;
+            self.send(&Message::Join {
+                name: self.name.clone(),
+            });
+        }
+        let available = self.peer.get_available_bytes();
+        if available > 0 {
+            let res = self.peer.get_data(available);
+            if let Ok(data) = res.get(1).try_to::<PackedByteArray>() {
+                self.buffer.extend_from_slice(data.as_slice());
+            }
+        }
+        while let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line).to_string();
+            match Message::from_line(&line) {
+                Ok(message) => {
+                    let is_board = matches!(message, Message::Board { .. });
+                    self.handle(message);
+                    // 收到地图之后需要切换场景，剩下的消息留给下一帧
+                    if is_board {
+                        break;
+                    }
+                }
+                Err(e) => godot_warn!("{}", e),
+            }
+        }
+    }
+    #[func]
+    fn is_connected_to_relay(&self) -> bool {
+        self.peer.get_status() == Status::CONNECTED
+    }
+    /// 中继分配的玩家编号，0表示负责选择地图
+    #[func]
+    fn player(&self) -> i32 {
+        self.player
+    }
+    #[func]
+    fn opponent_name(&self) -> GString {
+        self.opponent_name.clone().into()
+    }
+    /// 选择地图并发送给对手
+    #[func]
+    fn send_board(&mut self, seed: i64, width: i32, height: i32, game_mode: i32) {
+        let message = Message::Board {
+            seed,
+            width,
+            height,
+            game_mode,
+        };
+        // 和对手收到时一样检查参数范围
+        if let Err(e) = Message::from_line(&message.to_line()) {
+            godot_error!("{}", e);
+            return;
+        }
+        self.board = Some((seed, width, height, game_mode));
+        self.send(&message);
+    }
+    /// 设置显示对手操作的地图，之后不是从岛屿出发的操作不会发出opponent_moved
+    #[func]
+    fn set_opponent_map(&mut self, map: Gd<GameMap>) {
+        self.opponent_map = Some(map);
+    }
+    /// 按照双方的地图参数设置地图，之后正常reset并生成，返回错误信息
+    #[func]
+    fn setup_map(&self, mut map: Gd<GameMap>) -> GString {
+        let Some((seed, width, height, game_mode)) = self.board else {
+            return "还没有地图参数".into();
+        };
+        map.bind_mut()
+            .use_seed_settings(width, height, game_mode, seed);
+        GString::new()
+    }
+    /// 连接到自己地图的user_moved，把操作发送给对手
+    #[func]
+    fn on_user_moved(
+        &mut self,
+        src_pos: Vector2i,
+        direction: Direction2D,
+        count: i32,
+        _mistake: bool,
+    ) {
+        self.send(&Message::Move {
+            x: src_pos.x,
+            y: src_pos.y,
+            direction,
+            count,
+        });
+    }
+    #[func]
+    fn send_finished(&mut self, seconds: f64) {
+        self.send(&Message::Finished { seconds });
+    }
+    fn send(&mut self, message: &Message) {
+        if !self.is_connected_to_relay() {
+            return;
+        }
+        let line = message.to_line() + "\n";
+        self.peer.put_data(PackedByteArray::from(line.as_bytes()));
+    }
+    /// 对手的操作是否在地图范围内并且从岛屿出发
+    fn is_valid_move(&self, src_pos: Vector2i) -> bool {
+        let Some((_, width, height, _)) = self.board else {
+            return false;
+        };
+        if src_pos.x >= width || src_pos.y >= height {
+            return false;
+        }
+        match &self.opponent_map {
+            Some(map) if map.is_instance_valid() => map.bind().islands.contains_key(src_pos),
+            _ => true,
+        }
+    }
+    fn handle(&mut self, message: Message) {
+        match message {
+            Message::Welcome { player } => {
+                self.player = player;
+                self.base_mut()
+                    .emit_signal("welcome".into(), &[Variant::from(player)]);
+            }
+            Message::Join { name } => {
+                self.opponent_name = name.clone();
+                self.base_mut().emit_signal(
+                    "opponent_joined".into(),
+                    &[GString::from(name).to_variant()],
+                );
+            }
+            Message::Board {
+                seed,
+                width,
+                height,
+                game_mode,
+            } => {
+                self.board = Some((seed, width, height, game_mode));
+                self.base_mut().emit_signal(
+                    "board_received".into(),
+                    &[
+                        Variant::from(seed),
+                        Variant::from(width),
+                        Variant::from(height),
+                        Variant::from(game_mode),
+                    ],
+                );
+            }
+            Message::Move {
+                x,
+                y,
+                direction,
+                count,
+            } => {
+                let src_pos = Vector2i::new(x, y);
+                if !self.is_valid_move(src_pos) {
+                    godot_warn!("丢弃对手的操作：({}, {})不是岛屿", x, y);
+                    return;
+                }
+                self.base_mut().emit_signal(
+                    "opponent_moved".into(),
+                    &[
+                        Variant::from(src_pos),
+                        Variant::from(direction),
+                        Variant::from(count),
+                    ],
+                );
+            }
+            Message::Finished { seconds } => {
+                self.base_mut()
+                    .emit_signal("opponent_finished".into(), &[Variant::from(seconds)]);
+            }
+            Message::Left => {
+                self.base_mut().emit_signal("opponent_left".into(), &[]);
+            }
+        }
+    }
+}
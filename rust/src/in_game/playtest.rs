@@ -0,0 +1,454 @@
+use super::board::{Board, BridgeRules};
+use super::game_map::{BridgeAction, Direction2D, GameMap};
+use super::invariants::check_generated;
+use super::island::Island;
+use super::puzzle::Puzzle;
+use super::solver::Solver;
+use godot::prelude::*;
+
+/// 一次点击：岛屿位置和方向，和玩家点击桥梁一样通过`GameMap::user_gen_bridge`执行
+pub type Click = (Vector2i, Direction2D);
+
+/// 按照答案搭桥需要的点击，每座桥点击一次
+pub fn clicks_for_solution(solution: &[(Vector2i, Direction2D, i32)]) -> Vec<Click> {
+    solution
+        .iter()
+        .flat_map(|(pos, direction, count)| (0..*count).map(move |_| (*pos, *direction)))
+        .collect()
+}
+
+/// 解析点击脚本：每行是`x y 方向`，例如`3 4 Right`，以`;`开头的行为注释
+pub fn parse_script(text: &str) -> Result<Vec<Click>, String> {
+    text.lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with(';'))
+        .map(|line| {
+            let err = || format!("无法解析：{}", line);
+            match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+                [x, y, direction] => Ok((
+                    Vector2i::new(x.parse().map_err(|_| err())?, y.parse().map_err(|_| err())?),
+                    Direction2D::from_name(direction).ok_or_else(err)?,
+                )),
+                _ => Err(err()),
+            }
+        })
+        .collect()
+}
+
+/// 点击脚本的文本格式，见`parse_script`
+pub fn script_text(clicks: &[Click]) -> String {
+    clicks
+        .iter()
+        .map(|(pos, direction)| format!("{} {} {}\n", pos.x, pos.y, direction.name()))
+        .collect()
+}
+
+/// 可以像玩家一样点击的地图，测试中用不需要引擎的地图代替`GameMap`
+pub trait ClickBoard {
+    fn click(&mut self, pos: Vector2i, direction: Direction2D) -> BridgeAction;
+}
+
+impl ClickBoard for GameMap {
+    fn click(&mut self, pos: Vector2i, direction: Direction2D) -> BridgeAction {
+        self.user_gen_bridge(pos, direction)
+    }
+}
+
+impl ClickBoard for Board {
+    fn click(&mut self, pos: Vector2i, direction: Direction2D) -> BridgeAction {
+        Board::click(self, pos, direction)
+    }
+}
+
+/// 依次执行点击，没有效果的点击(不是岛屿或者玩家无法这样搭桥)视为错误
+pub fn play_clicks(board: &mut impl ClickBoard, clicks: &[Click]) -> Result<(), String> {
+    for (i, (pos, direction)) in clicks.iter().enumerate() {
+        if board.click(*pos, *direction) == BridgeAction::Pass {
+            return Err(format!(
+                "第{}次点击无效：({}, {}) {}",
+                i + 1,
+                pos.x,
+                pos.y,
+                direction.name()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// 求解器机器人：求出答案之后像玩家一样逐座搭桥，返回点击次数
+pub fn solve_by_clicking(map: &mut GameMap) -> Result<usize, String> {
+    let Some(solution) = Solver::solve(&map.to_puzzle()) else {
+        return Err("无解或者太复杂".into());
+    };
+    let clicks = clicks_for_solution(&solution);
+    play_clicks(map, &clicks)?;
+    if !map.is_solved() {
+        return Err("按照答案搭桥之后没有完成".into());
+    }
+    Ok(clicks.len())
+}
+
+/// 快照：谜题文本，在地图上依次执行点击并记录每次点击的结果，最后画出桥梁
+///
+/// ```text
+/// size 5 3
+/// ...
+/// grid
+/// 3 . 2 . #
+/// ...
+/// clicks
+/// 0 0 Right Single
+/// bridges
+/// 3 - 2 . #
+/// ...
+/// solved false
+/// ```
+pub fn snapshot(
+    puzzle: &Puzzle,
+    board: &mut (impl ClickBoard + BridgeRules),
+    clicks: &[Click],
+) -> String {
+    let mut res = puzzle.to_text();
+    res.push_str("clicks\n");
+    res.push_str(&record_clicks(board, clicks));
+    res.push_str("bridges\n");
+    res.push_str(&puzzle.render_bridges(&board.current_bridges()));
+    res.push_str(&format!("solved {}\n", board.is_solved()));
+    res
+}
+
+/// 快照测试的用例：名字、宽、高、难度、种子、点击脚本，结果保存在`godot/tests/golden/名字.txt`。
+/// 脚本为`solve`时使用求解器的答案，为`cycle`时把答案的前三次点击各点击三次(加桥直到拆桥)
+pub const SNAPSHOT_CASES: &[(&str, i32, i32, i32, i64, &str)] = &[
+    ("5x5_m0_s1", 5, 5, 0, 1, "solve"),
+    ("5x5_m0_s1_cycle", 5, 5, 0, 1, "cycle"),
+    ("8x6_m1_s7", 8, 6, 1, 7, "solve"),
+    ("10x7_m2_s42", 10, 7, 2, 42, "solve"),
+    ("10x7_m2_s42_cycle", 10, 7, 2, 42, "cycle"),
+    ("12x9_m3_s2024", 12, 9, 3, 2024, "solve"),
+    ("7x7_m1_s3_miss", 7, 7, 1, 3, "0 0 Up\n6 6 Down\n3 3 Left\n"),
+];
+
+/// 用例的点击，见`SNAPSHOT_CASES`
+pub fn case_clicks(puzzle: &Puzzle, script: &str) -> Result<Vec<Click>, String> {
+    let solution_clicks = || match Solver::solve(puzzle) {
+        Some(solution) => Ok(clicks_for_solution(&solution)),
+        None => Err("无解或者太复杂".to_string()),
+    };
+    match script {
+        "solve" => solution_clicks(),
+        "cycle" => Ok(solution_clicks()?
+            .into_iter()
+            .take(3)
+            .flat_map(|click| [click; 3])
+            .collect()),
+        _ => parse_script(script),
+    }
+}
+
+/// 不需要引擎的快照，和游戏中按照种子生成地图并点击的结果相同
+pub fn board_snapshot(name: &str) -> Result<String, String> {
+    let (_, width, height, game_mode, seed, script) = SNAPSHOT_CASES
+        .iter()
+        .find(|c| c.0 == name)
+        .ok_or_else(|| format!("没有用例{}", name))?;
+    let puzzle = GameMap::seeded_puzzle(*width, *height, *game_mode, *seed)?;
+    let clicks = case_clicks(&puzzle, script)?;
+    Ok(snapshot(&puzzle, &mut Board::from_puzzle(&puzzle), &clicks))
+}
+
+/// 依次执行点击，每行为一次点击和它的结果，点击空白格子的结果为`Pass`
+pub fn record_clicks(board: &mut impl ClickBoard, clicks: &[Click]) -> String {
+    clicks
+        .iter()
+        .map(|(pos, direction)| {
+            let action = board.click(*pos, *direction);
+            format!("{} {} {} {:?}\n", pos.x, pos.y, direction.name(), action)
+        })
+        .collect()
+}
+
+/// 按照种子生成不在场景树中的地图，用完之后调用`dispose_map`
+pub fn generate_map(
+    width: i32,
+    height: i32,
+    game_mode: i32,
+    seed: i64,
+) -> Result<Gd<GameMap>, String> {
+    let mut map = GameMap::create(width, height);
+    let exit = {
+        let mut map = map.bind_mut();
+        map.use_seed_settings(width, height, game_mode, seed);
+        map.generate()
+    };
+    if !map.bind().is_ready {
+        dispose_map(map);
+        return Err(format!("种子{}生成失败：{}", seed, exit));
+    }
+    Ok(map)
+}
+
+/// 释放地图和没有加入场景树的岛屿
+pub fn dispose_map(map: Gd<GameMap>) {
+    for (_, island) in map.bind().islands.iter_shared() {
+        island.to::<Gd<Island>>().free();
+    }
+    map.free();
+}
+
+/// 无界面试玩：不需要场景树和渲染，按照坐标执行玩家的操作，或者让机器人完成整局游戏，
+/// 用于自动测试生成的地图都可以像玩家一样完成
+///
+/// 结果为`{solved, moves, message}`，message为空表示没有错误
+#[derive(GodotClass, Debug)]
+#[class(init, base = RefCounted)]
+pub struct Playtest {
+    base: Base<RefCounted>,
+}
+
+#[godot_api]
+impl Playtest {
+    /// 按照种子生成地图，返回的地图不在场景树中，失败时为null，用完之后调用`dispose`
+    #[func]
+    fn generate(width: i32, height: i32, game_mode: i32, seed: i64) -> Option<Gd<GameMap>> {
+        match generate_map(width, height, game_mode, seed) {
+            Ok(map) => Some(map),
+            Err(e) => {
+                godot_error!("{}", e);
+                None
+            }
+        }
+    }
+    #[func]
+    fn dispose(map: Gd<GameMap>) {
+        dispose_map(map);
+    }
+    /// 用求解器机器人完成地图
+    #[func]
+    fn run_solver_bot(mut map: Gd<GameMap>) -> Dictionary {
+        let res = solve_by_clicking(&mut map.bind_mut());
+        Self::report(&map, res)
+    }
+    /// 依次执行脚本中的点击，见`parse_script`
+    #[func]
+    fn run_script(mut map: Gd<GameMap>, script: GString) -> Dictionary {
+        let res = parse_script(&script.to_string()).and_then(|clicks| {
+            play_clicks(&mut *map.bind_mut(), &clicks)?;
+            Ok(clicks.len())
+        });
+        Self::report(&map, res)
+    }
+    /// 求解器答案的点击脚本，无解时为空
+    #[func]
+    fn solution_script(map: Gd<GameMap>) -> GString {
+        match Solver::solve(&map.bind().to_puzzle()) {
+            Some(solution) => script_text(&clicks_for_solution(&solution)).into(),
+            None => GString::new(),
+        }
+    }
+    /// 执行点击脚本并返回快照，见`snapshot`，脚本无法解析时为空
+    #[func]
+    fn snapshot(mut map: Gd<GameMap>, script: GString) -> GString {
+        match parse_script(&script.to_string()) {
+            Ok(clicks) => {
+                let puzzle = map.bind().to_puzzle();
+                snapshot(&puzzle, &mut *map.bind_mut(), &clicks).into()
+            }
+            Err(e) => {
+                godot_error!("{}", e);
+                GString::new()
+            }
+        }
+    }
+    /// 快照测试的用例名字，见`SNAPSHOT_CASES`
+    #[func]
+    fn snapshot_cases() -> PackedStringArray {
+        SNAPSHOT_CASES.iter().map(|c| GString::from(c.0)).collect()
+    }
+    /// 按照用例生成地图并点击，返回快照，失败时为空。和`board_snapshot`不同时报告错误
+    #[func]
+    fn snapshot_case(name: GString) -> GString {
+        let name = name.to_string();
+        let Some((_, width, height, game_mode, seed, script)) =
+            SNAPSHOT_CASES.iter().find(|c| c.0 == name)
+        else {
+            godot_error!("没有用例{}", name);
+            return GString::new();
+        };
+        let res = generate_map(*width, *height, *game_mode, *seed).and_then(|mut map| {
+            let puzzle = map.bind().to_puzzle();
+            let res = case_clicks(&puzzle, script)
+                .map(|clicks| snapshot(&puzzle, &mut *map.bind_mut(), &clicks));
+            dispose_map(map);
+            res
+        });
+        match res {
+            Ok(text) => {
+                // cargo test使用不需要引擎的快照，两者不同时golden文件只对其中一个有效
+                if board_snapshot(&name).as_ref() != Ok(&text) {
+                    godot_error!("{}：游戏中的快照和不需要引擎的快照不同", name);
+                }
+                text.into()
+            }
+            Err(e) => {
+                godot_error!("{}：{}", name, e);
+                GString::new()
+            }
+        }
+    }
+    /// 从first_seed开始生成count个地图并用求解器机器人完成，返回失败的种子
+    #[func]
+    fn check_seeds(
+        width: i32,
+        height: i32,
+        game_mode: i32,
+        first_seed: i64,
+        count: i32,
+    ) -> PackedInt64Array {
+        Self::check_each(width, height, game_mode, first_seed, count, |map| {
+            solve_by_clicking(map).map(|_| ())
+        })
+    }
+    /// 从first_seed开始生成count个地图并检查生成的地图的性质，返回不满足的种子，
+    /// 见`invariants::check_generated`
+    #[func]
+    fn check_properties(
+        width: i32,
+        height: i32,
+        game_mode: i32,
+        first_seed: i64,
+        count: i32,
+    ) -> PackedInt64Array {
+        Self::check_each(width, height, game_mode, first_seed, count, |map| {
+            check_generated(&map.to_puzzle())
+        })
+    }
+    fn check_each(
+        width: i32,
+        height: i32,
+        game_mode: i32,
+        first_seed: i64,
+        count: i32,
+        check: impl Fn(&mut GameMap) -> Result<(), String>,
+    ) -> PackedInt64Array {
+        let mut failed = PackedInt64Array::new();
+        for seed in first_seed..first_seed + count as i64 {
+            let res = generate_map(width, height, game_mode, seed).and_then(|mut map| {
+                let res = check(&mut map.bind_mut());
+                dispose_map(map);
+                res
+            });
+            if let Err(e) = res {
+                godot_warn!("种子{}：{}", seed, e);
+                failed.push(seed);
+            }
+        }
+        failed
+    }
+    fn report(map: &Gd<GameMap>, res: Result<usize, String>) -> Dictionary {
+        let (moves, message) = match res {
+            Ok(moves) => (moves as i32, String::new()),
+            Err(e) => (-1, e),
+        };
+        dict! {
+            "solved": map.bind().is_solved(),
+            "moves": moves,
+            "message": message,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clicks_for_solution() {
+        let p = Vector2i::new(1, 2);
+        let solution = [(p, Direction2D::Right, 2), (p, Direction2D::Down, 1)];
+        assert_eq!(
+            clicks_for_solution(&solution),
+            vec![
+                (p, Direction2D::Right),
+                (p, Direction2D::Right),
+                (p, Direction2D::Down)
+            ]
+        );
+    }
+    #[test]
+    fn test_parse_script() {
+        let clicks = parse_script("; 注释\n0 0 Right\n\n3 4 Up\n").unwrap();
+        assert_eq!(
+            clicks,
+            vec![
+                (Vector2i::new(0, 0), Direction2D::Right),
+                (Vector2i::new(3, 4), Direction2D::Up)
+            ]
+        );
+        assert_eq!(parse_script(&script_text(&clicks)), Ok(clicks));
+        assert!(parse_script("0 0").is_err());
+        assert!(parse_script("0 x Right").is_err());
+    }
+
+    /// 只有岛屿的地图，从岛屿出发的点击都能搭桥
+    struct IslandsOnly(Vec<Vector2i>);
+
+    impl ClickBoard for IslandsOnly {
+        fn click(&mut self, pos: Vector2i, _: Direction2D) -> BridgeAction {
+            if self.0.contains(&pos) {
+                BridgeAction::Single
+            } else {
+                BridgeAction::Pass
+            }
+        }
+    }
+
+    #[test]
+    fn test_play_clicks() {
+        let mut board = IslandsOnly(vec![Vector2i::new(0, 0)]);
+        let clicks = parse_script("0 0 Right\n0 0 Right\n").unwrap();
+        assert_eq!(play_clicks(&mut board, &clicks), Ok(()));
+        // 点击空白格子
+        let clicks = parse_script("0 0 Right\n2 3 Up\n").unwrap();
+        assert_eq!(
+            play_clicks(&mut board, &clicks),
+            Err("第2次点击无效：(2, 3) Up".into())
+        );
+    }
+    #[test]
+    fn test_record_clicks() {
+        let mut board = IslandsOnly(vec![Vector2i::new(0, 0)]);
+        let clicks = parse_script("0 0 Up\n6 6 Down\n").unwrap();
+        assert_eq!(
+            record_clicks(&mut board, &clicks),
+            "0 0 Up Single\n6 6 Down Pass\n"
+        );
+    }
+    #[test]
+    fn test_case_clicks() {
+        let puzzle = Puzzle::from_text("size 3 1\ngrid\n2 . 2\n").unwrap();
+        let right = (Vector2i::new(0, 0), Direction2D::Right);
+        assert_eq!(case_clicks(&puzzle, "solve"), Ok(vec![right; 2]));
+        assert_eq!(case_clicks(&puzzle, "cycle"), Ok(vec![right; 6]));
+        assert_eq!(case_clicks(&puzzle, "0 0 Right\n"), Ok(vec![right]));
+    }
+    /// 和`godot/tests/golden`中的文件比较，生成器有意修改之后用`BLESS=1 cargo test`重新生成，
+    /// 游戏中的结果由`godot/tests/snapshots.gd`比较
+    #[test]
+    fn test_golden() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../godot/tests/golden/");
+        let bless = std::env::var("BLESS").is_ok_and(|v| v == "1");
+        for (name, ..) in SNAPSHOT_CASES.iter() {
+            let actual = board_snapshot(name).unwrap();
+            let path = format!("{}{}.txt", dir, name);
+            if bless {
+                std::fs::write(&path, actual).unwrap();
+                continue;
+            }
+            let expected = std::fs::read_to_string(&path)
+                .unwrap_or_else(|_| panic!("缺少{}，使用BLESS=1生成", path));
+            assert_eq!(expected, actual, "{}和快照不同", path);
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use super::game_map::Direction2D;
+use crate::common::settings::int_range;
+
+/// 中继服务器的默认端口
+pub const DEFAULT_PORT: u16 = 7878;
+
+/// 联网对战的消息，每条消息是一行文本，第一个单词是消息类型
+///
+/// ```text
+/// join 玩家名
+/// welcome 0
+/// board 12345 10 7 2
+/// move 3 4 Right 2
+/// finished 63.250
+/// left
+/// ```
+///
+/// 客户端连接之后先发送`join`，中继回复`welcome`，两个客户端都加入之后中继把对方的`join`
+/// 转发给对方。玩家0发送`board`，之后两边按照相同的种子生成地图，互相转发`move`和`finished`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// 加入对战，玩家名不能包含空白
+    Join { name: String },
+    /// 中继分配的玩家编号，玩家0负责选择地图
+    Welcome { player: i32 },
+    /// 地图参数，见`GameMap::use_seed_settings`
+    Board {
+        seed: i64,
+        width: i32,
+        height: i32,
+        game_mode: i32,
+    },
+    /// 把桥梁数量设置为count，见`GameMap::user_set_bridge`
+    Move {
+        x: i32,
+        y: i32,
+        direction: Direction2D,
+        count: i32,
+    },
+    /// 完成谜题，seconds为用时
+    Finished { seconds: f64 },
+    /// 对手断开连接
+    Left,
+}
+
+impl Message {
+    /// 不包含换行符
+    pub fn to_line(&self) -> String {
+        match self {
+            Message::Join { name } => format!("join {}", name),
+            Message::Welcome { player } => format!("welcome {}", player),
+            Message::Board {
+                seed,
+                width,
+                height,
+                game_mode,
+            } => format!("board {} {} {} {}", seed, width, height, game_mode),
+            Message::Move {
+                x,
+                y,
+                direction,
+                count,
+            } => format!("move {} {} {} {}", x, y, direction.name(), count),
+            Message::Finished { seconds } => format!("finished {:.3}", seconds),
+            Message::Left => "left".to_string(),
+        }
+    }
+    pub fn from_line(line: &str) -> Result<Self, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let err = || format!("无法解析：{}", line);
+        let message = match words.as_slice() {
+            ["join", name] => Message::Join {
+                name: name.to_string(),
+            },
+            ["welcome", player] => Message::Welcome {
+                player: player.parse().map_err(|_| err())?,
+            },
+            ["board", seed, width, height, game_mode] => Message::Board {
+                seed: seed.parse().map_err(|_| err())?,
+                width: width.parse().map_err(|_| err())?,
+                height: height.parse().map_err(|_| err())?,
+                game_mode: game_mode.parse().map_err(|_| err())?,
+            },
+            ["move", x, y, direction, count] => Message::Move {
+                x: x.parse().map_err(|_| err())?,
+                y: y.parse().map_err(|_| err())?,
+                direction: Direction2D::from_name(direction).ok_or_else(err)?,
+                count: count.parse().map_err(|_| err())?,
+            },
+            ["finished", seconds] => Message::Finished {
+                seconds: seconds.parse().map_err(|_| err())?,
+            },
+            ["left"] => Message::Left,
+            _ => return Err(err()),
+        };
+        message.check().map_err(|e| format!("{}：{}", e, line))?;
+        Ok(message)
+    }
+    /// 检查参数是否在设置的合法范围内，见`Settings`，对手发来的地图参数会直接用于生成地图
+    fn check(&self) -> Result<(), String> {
+        let in_range = |key: &str, v: i32| {
+            let (min, max) = int_range(key).unwrap();
+            (min..=max).contains(&v)
+        };
+        match *self {
+            Message::Board {
+                width,
+                height,
+                game_mode,
+                ..
+            } => {
+                if !in_range("map_size", width) || !in_range("map_size", height) {
+                    return Err("地图大小超出范围".into());
+                }
+                if !in_range("game_mode", game_mode) {
+                    return Err("难度超出范围".into());
+                }
+            }
+            Message::Move { x, y, count, .. } => {
+                let (_, max_bridges) = int_range("max_bridges_per_link").unwrap();
+                if x < 0 || y < 0 || !(0..=max_bridges).contains(&count) {
+                    return Err("操作超出范围".into());
+                }
+            }
+            Message::Finished { seconds } if !seconds.is_finite() => {
+                return Err("用时不是有限的数".into());
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// 玩家名中的空白替换为下划线，空的名字使用默认名字
+pub fn sanitize_name(name: &str) -> String {
+    let name: String = name
+        .trim()
+        .chars()
+        .map(|c| if c.is_whitespace() { '_' } else { c })
+        .collect();
+    if name.is_empty() {
+        "player".to_string()
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_round_trip() {
+        let messages = [
+            Message::Join {
+                name: "小明".to_string(),
+            },
+            Message::Welcome { player: 1 },
+            Message::Board {
+                seed: -42,
+                width: 10,
+                height: 7,
+                game_mode: 2,
+            },
+            Message::Move {
+                x: 3,
+                y: 4,
+                direction: Direction2D::UpRight,
+                count: 2,
+            },
+            Message::Finished { seconds: 63.25 },
+            Message::Left,
+        ];
+        for message in messages {
+            assert_eq!(Message::from_line(&message.to_line()), Ok(message));
+        }
+        assert!(Message::from_line("move 1 2 Forward 1").is_err());
+        assert!(Message::from_line("board 1 2 3").is_err());
+        assert!(Message::from_line("").is_err());
+        // 超出设置范围的地图参数和操作
+        assert!(Message::from_line("board 1 0 7 2").is_err());
+        assert!(Message::from_line("board 1 10 100000 2").is_err());
+        assert!(Message::from_line("board 1 10 7 21").is_err());
+        assert!(Message::from_line("board 1 10 7 -1").is_err());
+        assert!(Message::from_line("move -1 2 Up 1").is_err());
+        assert!(Message::from_line("move 1 2 Up 4").is_err());
+        assert!(Message::from_line("finished NaN").is_err());
+        assert_eq!(sanitize_name(" a b "), "a_b");
+        assert_eq!(sanitize_name(""), "player");
+    }
+}
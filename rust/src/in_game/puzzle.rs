@@ -0,0 +1,441 @@
+use super::game_map::{Direction2D, MapVariant};
+use crate::common::settings::int_range;
+use godot::prelude::*;
+use std::collections::HashSet;
+
+/// 障碍物
+const OBSTACLE: &'static str = "#";
+/// 空格子
+const EMPTY: &'static str = ".";
+/// 不属于地图的格子
+const HOLE: &'static str = "_";
+/// 注释行的开头
+const COMMENT: &'static str = ";";
+/// 岛屿数量和颜色之间的分隔符
+const COLOR_SEPARATOR: char = ':';
+/// 隐藏的岛屿数量
+const UNKNOWN: &'static str = "?";
+
+/// 谜题中的岛屿
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PuzzleIsland {
+    pub pos: Vector2i,
+    /// 需要的桥梁数量
+    pub count: i32,
+    /// 颜色编号
+    pub color_id: i32,
+    /// 是否隐藏需要的桥梁数量
+    pub hidden: bool,
+}
+
+/// 谜题，可以和文本格式以及JSON格式互相转换
+///
+/// ```text
+/// size 5 3
+/// variant Square
+/// wrap 0
+/// max_bridges 2
+/// grid
+/// 3 . 2 . #
+/// . # . . .
+/// 2 . 4 . 1
+/// ```
+///
+/// `grid`之后每行是地图的一行，格子之间用空格分隔：`.`为空格子，`#`为障碍物，
+/// `_`为不属于地图的格子，数字为岛屿需要的桥梁数量，彩色岛屿在数字后面加上`:颜色编号`，
+/// 例如`3:1`，隐藏数量的岛屿为`?`。以`;`开头的行为注释
+///
+/// JSON格式的`grid`为每一行的字符串，其他字段和文本格式相同，`size`为`[宽, 高]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Puzzle {
+    pub width: i32,
+    pub height: i32,
+    pub variant: MapVariant,
+    pub wrap: bool,
+    pub max_bridges_per_link: i32,
+    pub islands: Vec<PuzzleIsland>,
+    pub obstacles: Vec<Vector2i>,
+    /// 不属于地图的格子，用于非矩形地图
+    pub holes: Vec<Vector2i>,
+}
+
+impl Puzzle {
+    /// 网格的每一个格子
+    fn grid_cells(&self) -> Vec<Vec<String>> {
+        let mut grid = vec![vec![EMPTY.to_string(); self.width as usize]; self.height as usize];
+        for p in self.obstacles.iter() {
+            grid[p.y as usize][p.x as usize] = OBSTACLE.to_string();
+        }
+        for p in self.holes.iter() {
+            grid[p.y as usize][p.x as usize] = HOLE.to_string();
+        }
+        for island in self.islands.iter() {
+            let p = island.pos;
+            let count = if island.hidden {
+                UNKNOWN.to_string()
+            } else {
+                island.count.to_string()
+            };
+            grid[p.y as usize][p.x as usize] = if island.color_id == 0 {
+                count
+            } else {
+                format!("{}{}{}", count, COLOR_SEPARATOR, island.color_id)
+            };
+        }
+        grid
+    }
+    /// 网格的每一行
+    fn grid_rows(&self) -> Vec<String> {
+        self.grid_cells().iter().map(|row| row.join(" ")).collect()
+    }
+    /// 解析网格的每一行
+    fn parse_grid<'a>(&mut self, rows: impl Iterator<Item = &'a str>) -> Result<(), String> {
+        self.check_header()?;
+        let mut y = 0;
+        for line in rows {
+            if y >= self.height {
+                return Err(format!("行数超过{}", self.height));
+            }
+            let cells: Vec<&str> = line.split_whitespace().collect();
+            if cells.len() != self.width as usize {
+                return Err(format!("第{}行应该有{}个格子", y + 1, self.width));
+            }
+            for (x, cell) in cells.iter().enumerate() {
+                let p = Vector2i::new(x as i32, y);
+                match *cell {
+                    EMPTY => {}
+                    OBSTACLE => self.obstacles.push(p),
+                    HOLE => self.holes.push(p),
+                    island => self.islands.push(parse_island(p, island)?),
+                }
+            }
+            y += 1;
+        }
+        if y != self.height {
+            return Err(format!("行数应该为{}", self.height));
+        }
+        self.check_cells()
+    }
+    /// 地图大小和桥梁数量上限在设置的范围内。手工制作的谜题可以比随机生成的地图小，
+    /// 例如入门关卡，所以地图大小只检查上限
+    fn check_header(&self) -> Result<(), String> {
+        let (_, max_size) = int_range("map_size").unwrap();
+        if !(1..=max_size).contains(&self.width) || !(1..=max_size).contains(&self.height) {
+            return Err(format!("地图大小必须在1到{}之间", max_size));
+        }
+        let (min, max) = int_range("max_bridges_per_link").unwrap();
+        if !(min..=max).contains(&self.max_bridges_per_link) {
+            return Err(format!("max_bridges必须在{}到{}之间", min, max));
+        }
+        Ok(())
+    }
+    /// 岛屿和障碍物必须在地图中，不属于地图的格子被当作地图外，上面的岛屿无法到达
+    fn check_cells(&self) -> Result<(), String> {
+        let holes: HashSet<Vector2i> = self.holes.iter().copied().collect();
+        let positions = self
+            .islands
+            .iter()
+            .map(|i| i.pos)
+            .chain(self.obstacles.iter().copied());
+        let mut used = HashSet::new();
+        for p in positions {
+            if p.x < 0 || p.y < 0 || p.x >= self.width || p.y >= self.height || holes.contains(&p) {
+                return Err(format!("({}, {})不在地图中", p.x, p.y));
+            }
+            if !used.insert(p) {
+                return Err(format!("({}, {})有多个岛屿或者障碍物", p.x, p.y));
+            }
+        }
+        Ok(())
+    }
+    fn empty() -> Self {
+        Puzzle {
+            width: 0,
+            height: 0,
+            variant: MapVariant::Square,
+            wrap: false,
+            max_bridges_per_link: 2,
+            islands: vec![],
+            obstacles: vec![],
+            holes: vec![],
+        }
+    }
+    pub fn to_text(&self) -> String {
+        let mut res = format!(
+            "size {} {}\nvariant {}\nwrap {}\nmax_bridges {}\ngrid\n",
+            self.width,
+            self.height,
+            self.variant.name(),
+            self.wrap as i32,
+            self.max_bridges_per_link
+        );
+        for row in self.grid_rows().iter() {
+            res.push_str(row);
+            res.push('\n');
+        }
+        res
+    }
+    /// 画出桥梁的网格，每座桥为(起点岛屿位置, 方向, 桥梁数量)，桥梁经过的空格子显示为
+    /// `-` `|` `/` `\`，两座桥显示为`=` `"` `Z` `N`，三座桥显示为`≡` `⦀` `⋰` `⋱`，用于快照测试
+    pub fn render_bridges(&self, bridges: &[(Vector2i, Direction2D, i32)]) -> String {
+        let mut grid = self.grid_cells();
+        let islands: HashSet<Vector2i> = self.islands.iter().map(|i| i.pos).collect();
+        for (pos, direction, count) in bridges.iter() {
+            if *count <= 0 {
+                continue;
+            }
+            let glyph = bridge_glyph(*direction, *count);
+            let mut p = *pos;
+            loop {
+                p = p + direction.to_vector();
+                if self.wrap {
+                    p = Vector2i::new(p.x.rem_euclid(self.width), p.y.rem_euclid(self.height));
+                }
+                if p == *pos || p.x < 0 || p.y < 0 || p.x >= self.width || p.y >= self.height {
+                    break;
+                }
+                if islands.contains(&p) {
+                    break;
+                }
+                let cell = &mut grid[p.y as usize][p.x as usize];
+                if cell == EMPTY {
+                    *cell = glyph.to_string();
+                }
+            }
+        }
+        let mut res = String::new();
+        for row in grid.iter() {
+            res.push_str(&row.join(" "));
+            res.push('\n');
+        }
+        res
+    }
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut puzzle = Self::empty();
+        let mut lines = text
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && !l.starts_with(COMMENT));
+        // 头部
+        loop {
+            let Some(line) = lines.next() else {
+                return Err("缺少grid".into());
+            };
+            let mut words = line.split_whitespace();
+            let key = words.next().unwrap();
+            let values: Vec<&str> = words.collect();
+            match (key, values.as_slice()) {
+                ("grid", []) => break,
+                ("size", [w, h]) => {
+                    puzzle.width = parse_i32(w)?;
+                    puzzle.height = parse_i32(h)?;
+                }
+                ("variant", [v]) => {
+                    puzzle.variant =
+                        MapVariant::from_name(v).ok_or_else(|| format!("未知的网格类型：{}", v))?;
+                }
+                ("wrap", [v]) => puzzle.wrap = parse_i32(v)? != 0,
+                ("max_bridges", [v]) => puzzle.max_bridges_per_link = parse_i32(v)?,
+                _ => return Err(format!("无法解析：{}", line)),
+            }
+        }
+        // 网格
+        puzzle.parse_grid(lines)?;
+        Ok(puzzle)
+    }
+    /// 转换为JSON格式使用的Dictionary
+    pub fn to_dict(&self) -> Dictionary {
+        let mut grid = VariantArray::new();
+        for row in self.grid_rows() {
+            grid.push(row.to_variant());
+        }
+        dict! {
+            "size": array![self.width.to_variant(), self.height.to_variant()],
+            "variant": GString::from(self.variant.name()),
+            "wrap": self.wrap,
+            "max_bridges": self.max_bridges_per_link,
+            "grid": grid,
+        }
+    }
+    /// 从JSON解析出的Dictionary转换，JSON中的数字都是浮点数
+    pub fn from_dict(dict: &Dictionary) -> Result<Self, String> {
+        let mut puzzle = Self::empty();
+        let get = |key: &str| dict.get(key).ok_or_else(|| format!("缺少{}", key));
+        let to_i32 = |v: Variant| {
+            v.try_to::<f64>()
+                .map(|f| f as i32)
+                .map_err(|_| format!("不是数字：{}", v))
+        };
+        let size = get("size")?
+            .try_to::<VariantArray>()
+            .map_err(|_| "size必须为数组".to_string())?;
+        if size.len() != 2 {
+            return Err("size必须为[宽, 高]".into());
+        }
+        puzzle.width = to_i32(size.get(0))?;
+        puzzle.height = to_i32(size.get(1))?;
+        if let Some(v) = dict.get("variant") {
+            let name = v.to_string();
+            puzzle.variant =
+                MapVariant::from_name(&name).ok_or_else(|| format!("未知的网格类型：{}", name))?;
+        }
+        if let Some(v) = dict.get("wrap") {
+            puzzle.wrap = v
+                .try_to::<bool>()
+                .map_err(|_| "wrap必须为布尔值".to_string())?;
+        }
+        if let Some(v) = dict.get("max_bridges") {
+            puzzle.max_bridges_per_link = to_i32(v)?;
+        }
+        let rows: Vec<String> = get("grid")?
+            .try_to::<VariantArray>()
+            .map_err(|_| "grid必须为数组".to_string())?
+            .iter_shared()
+            .map(|v| v.to_string())
+            .collect();
+        puzzle.parse_grid(rows.iter().map(|r| r.as_str()))?;
+        Ok(puzzle)
+    }
+}
+
+fn parse_island(pos: Vector2i, s: &str) -> Result<PuzzleIsland, String> {
+    let (count, color_id) = match s.split_once(COLOR_SEPARATOR) {
+        Some((count, color_id)) => (count, parse_i32(color_id)?),
+        None => (s, 0),
+    };
+    let hidden = count == UNKNOWN;
+    let count = if hidden { 0 } else { parse_i32(count)? };
+    if count < 0 || color_id < 0 {
+        return Err(format!("无法解析岛屿：{}", s));
+    }
+    Ok(PuzzleIsland {
+        pos,
+        count,
+        color_id,
+        hidden,
+    })
+}
+
+/// 每个方向上一到三座桥的符号
+fn bridge_glyphs(direction: Direction2D) -> [&'static str; 3] {
+    match direction {
+        Direction2D::Left | Direction2D::Right => ["-", "=", "≡"],
+        Direction2D::Up | Direction2D::Down => ["|", "\"", "⦀"],
+        Direction2D::UpRight | Direction2D::DownLeft => ["/", "Z", "⋰"],
+        Direction2D::UpLeft | Direction2D::DownRight => ["\\", "N", "⋱"],
+    }
+}
+
+fn bridge_glyph(direction: Direction2D, count: i32) -> &'static str {
+    bridge_glyphs(direction)[(count.clamp(1, 3) - 1) as usize]
+}
+
+fn parse_i32(s: &str) -> Result<i32, String> {
+    s.parse::<i32>().map_err(|_| format!("不是整数：{}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_round_trip() {
+        let text = "size 3 2\nvariant Hex\nwrap 1\nmax_bridges 3\ngrid\n3 # 2:1\n_ ? 1:1\n";
+        let puzzle = Puzzle::from_text(text).unwrap();
+        assert_eq!(puzzle.variant, MapVariant::Hex);
+        assert!(puzzle.wrap);
+        assert_eq!(puzzle.obstacles, vec![Vector2i::new(1, 0)]);
+        assert_eq!(puzzle.holes, vec![Vector2i::new(0, 1)]);
+        assert_eq!(
+            puzzle.islands,
+            vec![
+                PuzzleIsland {
+                    pos: Vector2i::new(0, 0),
+                    count: 3,
+                    color_id: 0,
+                    hidden: false
+                },
+                PuzzleIsland {
+                    pos: Vector2i::new(2, 0),
+                    count: 2,
+                    color_id: 1,
+                    hidden: false
+                },
+                PuzzleIsland {
+                    pos: Vector2i::new(1, 1),
+                    count: 0,
+                    color_id: 0,
+                    hidden: true
+                },
+                PuzzleIsland {
+                    pos: Vector2i::new(2, 1),
+                    count: 1,
+                    color_id: 1,
+                    hidden: false
+                }
+            ]
+        );
+        assert_eq!(puzzle.to_text(), text);
+    }
+    #[test]
+    fn test_render_bridges() {
+        let puzzle = Puzzle::from_text("size 4 3\ngrid\n3 . . 1\n. # . .\n2 . . 1\n").unwrap();
+        let bridges = [
+            (Vector2i::new(0, 0), Direction2D::Right, 1),
+            (Vector2i::new(0, 0), Direction2D::Down, 2),
+            (Vector2i::new(3, 0), Direction2D::Down, 1),
+        ];
+        assert_eq!(
+            puzzle.render_bridges(&bridges),
+            "3 - - 1\n\" # . |\n2 . . 1\n"
+        );
+    }
+    #[test]
+    fn test_render_triple_bridges() {
+        let puzzle =
+            Puzzle::from_text("size 3 3\nmax_bridges 3\ngrid\n3 . 3\n. . .\n3 . .\n").unwrap();
+        let bridges = [
+            (Vector2i::new(0, 0), Direction2D::Right, 3),
+            (Vector2i::new(0, 0), Direction2D::Down, 3),
+        ];
+        assert_eq!(puzzle.render_bridges(&bridges), "3 ≡ 3\n⦀ . .\n3 . .\n");
+        // 从画出的符号读回桥梁数量，每个方向上不同数量的符号都不同，并且不会和谜题中的格子混淆
+        let empty = Puzzle::from_text("size 3 3\ngrid\n. . .\n. . .\n. . .\n").unwrap();
+        let center = Vector2i::new(1, 1);
+        for direction in MapVariant::Diagonal.directions() {
+            for count in 1..=3 {
+                let text =
+                    empty.render_bridges(&[(center - direction.to_vector(), *direction, count)]);
+                let glyph = text.lines().nth(1).unwrap().split(' ').nth(1).unwrap();
+                let read = bridge_glyphs(*direction).iter().position(|g| *g == glyph);
+                assert_eq!(read, Some(count as usize - 1));
+                assert!(Puzzle::from_text(&format!("size 1 1\ngrid\n{}\n", glyph)).is_err());
+            }
+        }
+    }
+    #[test]
+    fn test_text_error() {
+        assert!(Puzzle::from_text("size 2 1\ngrid\n1 . .\n").is_err());
+        assert!(Puzzle::from_text("size 2 1\n").is_err());
+        assert!(Puzzle::from_text("size 1 1\ngrid\nx\n").is_err());
+        assert!(Puzzle::from_text("size 1 1\ngrid\n2:\n").is_err());
+        // 超出设置的范围
+        assert!(Puzzle::from_text("size 0 1\ngrid\n").is_err());
+        assert!(Puzzle::from_text("size 51 1\ngrid\n").is_err());
+        assert!(Puzzle::from_text("size 1 1\nmax_bridges 0\ngrid\n1\n").is_err());
+        assert!(Puzzle::from_text("size 1 1\nmax_bridges 4\ngrid\n1\n").is_err());
+        assert!(Puzzle::from_text("size 1 1\nmax_bridges 3\ngrid\n1\n").is_ok());
+        // 岛屿或者障碍物在不属于地图的格子上
+        let mut p = Puzzle::from_text("size 3 1\ngrid\n1 _ 1\n").unwrap();
+        assert_eq!(p.check_cells(), Ok(()));
+        p.islands[1].pos = Vector2i::new(1, 0);
+        assert!(p.check_cells().is_err());
+        p.islands[1].pos = Vector2i::new(3, 0);
+        assert!(p.check_cells().is_err());
+        p.islands[1].pos = Vector2i::new(0, 0);
+        assert!(p.check_cells().is_err());
+        let mut p = Puzzle::from_text("size 3 1\ngrid\n1 _ 1\n").unwrap();
+        p.obstacles.push(Vector2i::new(1, 0));
+        assert!(p.check_cells().is_err());
+    }
+}
@@ -0,0 +1,426 @@
+use super::game_map::{BridgeAction, Direction2D, GameMap};
+use super::puzzle::Puzzle;
+use godot::engine::file_access::ModeFlags;
+use godot::engine::FileAccess;
+use godot::prelude::*;
+
+/// 文本格式中谜题和操作列表之间的分隔行
+const REPLAY_HEADER: &'static str = "replay";
+
+/// 回放中的一个操作
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReplayAction {
+    /// 把桥梁数量设置为count，见`GameMap::user_set_bridge`
+    Move {
+        src_pos: Vector2i,
+        direction: Direction2D,
+        count: i32,
+    },
+    Undo,
+    Hint,
+}
+
+/// 带时间(秒)的操作
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ReplayEvent {
+    time: f64,
+    action: ReplayAction,
+}
+
+impl ReplayEvent {
+    fn to_line(&self) -> String {
+        match self.action {
+            ReplayAction::Move {
+                src_pos,
+                direction,
+                count,
+            } => format!(
+                "{:.3} move {} {} {} {}",
+                self.time,
+                src_pos.x,
+                src_pos.y,
+                direction.name(),
+                count
+            ),
+            ReplayAction::Undo => format!("{:.3} undo", self.time),
+            ReplayAction::Hint => format!("{:.3} hint", self.time),
+        }
+    }
+    fn from_line(line: &str) -> Result<Self, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let err = || format!("无法解析：{}", line);
+        let Some((time, rest)) = words.split_first() else {
+            return Err(err());
+        };
+        let time = time.parse::<f64>().map_err(|_| err())?;
+        let action = match rest {
+            ["move", x, y, direction, count] => ReplayAction::Move {
+                src_pos: Vector2i::new(
+                    x.parse().map_err(|_| err())?,
+                    y.parse().map_err(|_| err())?,
+                ),
+                direction: Direction2D::from_name(direction).ok_or_else(err)?,
+                count: count.parse().map_err(|_| err())?,
+            },
+            ["undo"] => ReplayAction::Undo,
+            ["hint"] => ReplayAction::Hint,
+            _ => return Err(err()),
+        };
+        Ok(ReplayEvent { time, action })
+    }
+}
+
+/// 一局游戏的录像：谜题和按时间排序的操作
+///
+/// ```text
+/// size 5 3
+/// ...
+/// grid
+/// 3 . 2 . #
+/// ...
+/// replay
+/// 0.850 move 0 0 Right 1
+/// 1.200 undo
+/// 2.000 hint
+/// ```
+///
+/// `replay`之前是`Puzzle`的文本格式，之后每行是一个操作：时间、类型和参数
+#[derive(Debug, Clone, PartialEq)]
+struct Recording {
+    puzzle: Puzzle,
+    events: Vec<ReplayEvent>,
+}
+
+impl Recording {
+    fn to_text(&self) -> String {
+        let mut res = self.puzzle.to_text();
+        res.push_str(REPLAY_HEADER);
+        res.push('\n');
+        for event in self.events.iter() {
+            res.push_str(&event.to_line());
+            res.push('\n');
+        }
+        res
+    }
+    fn from_text(text: &str) -> Result<Self, String> {
+        let mut puzzle_lines = vec![];
+        let mut lines = text.lines().map(|l| l.trim());
+        for line in lines.by_ref() {
+            if line == REPLAY_HEADER {
+                break;
+            }
+            puzzle_lines.push(line);
+        }
+        let puzzle = Puzzle::from_text(&puzzle_lines.join("\n"))?;
+        let mut events = vec![];
+        for line in lines.filter(|l| !l.is_empty() && !l.starts_with(';')) {
+            let event = ReplayEvent::from_line(line)?;
+            if events
+                .last()
+                .is_some_and(|last: &ReplayEvent| last.time > event.time)
+            {
+                return Err(format!("操作的时间不是递增的：{}", line));
+            }
+            if let ReplayAction::Move { src_pos, .. } = event.action {
+                if !puzzle.islands.iter().any(|i| i.pos == src_pos) {
+                    return Err(format!("操作的起点不是岛屿：{}", line));
+                }
+            }
+            events.push(event);
+        }
+        Ok(Recording { puzzle, events })
+    }
+    fn duration(&self) -> f64 {
+        self.events.last().map_or(0.0, |e| e.time)
+    }
+}
+
+/// 录像：record为true时记录地图中玩家的操作，否则通过`GameMap`的操作回放录像，
+/// 回放的操作和玩家操作一样通过`render_bridge`显示
+///
+/// 回放之前需要用录像中的谜题生成地图，见`puzzle_text`
+#[derive(GodotClass, Debug)]
+#[class(init, base = Node)]
+pub struct Replay {
+    #[export]
+    map: Option<Gd<GameMap>>,
+    /// 记录地图的操作，只在进入场景树之前设置
+    #[init(default = false)]
+    #[export]
+    record: bool,
+    /// 回放速度的倍数
+    #[init(default = 1.0)]
+    #[export]
+    speed: f64,
+    /// 录像中的时间(秒)，场景树暂停时不计时
+    #[init(default = 0.0)]
+    #[export]
+    time: f64,
+    #[init(default = None)]
+    recording: Option<Recording>,
+    /// 已经回放的操作数量
+    #[init(default = 0)]
+    played: usize,
+    #[init(default = false)]
+    playing: bool,
+    /// 谜题完成之后不再记录
+    #[init(default = false)]
+    finished: bool,
+    base: Base<Node>,
+}
+
+#[godot_api]
+impl Replay {
+    /// 回放完所有操作
+    #[signal]
+    pub fn playback_finished() {}
+    /// 导出为文本格式，见`Recording`
+    #[func]
+    fn to_text(&self) -> GString {
+        match &self.recording {
+            Some(recording) => recording.to_text().into(),
+            None => GString::new(),
+        }
+    }
+    /// 保存文本格式，返回错误信息
+    #[func]
+    fn save_to(&self, path: GString) -> GString {
+        if self.recording.is_none() {
+            return "没有录像".into();
+        }
+        let Some(mut file) = FileAccess::open(path.clone(), ModeFlags::WRITE) else {
+            return format!("无法写入：{}", path).into();
+        };
+        file.store_string(self.to_text());
+        GString::new()
+    }
+    /// 从文本格式加载录像，返回错误信息
+    #[func]
+    fn load_text(&mut self, text: GString) -> GString {
+        match Recording::from_text(&text.to_string()) {
+            Ok(recording) => {
+                self.recording = Some(recording);
+                self.time = 0.0;
+                self.played = 0;
+                self.playing = false;
+                GString::new()
+            }
+            Err(e) => e.into(),
+        }
+    }
+    #[func]
+    fn load_file(&mut self, path: GString) -> GString {
+        let text = FileAccess::get_file_as_string(path.clone());
+        if text.is_empty() {
+            return format!("无法读取：{}", path).into();
+        }
+        self.load_text(text)
+    }
+    /// 录像中的谜题，用于回放之前生成地图
+    #[func]
+    fn puzzle_text(&self) -> GString {
+        match &self.recording {
+            Some(recording) => recording.puzzle.to_text().into(),
+            None => GString::new(),
+        }
+    }
+    #[func]
+    fn play(&mut self) {
+        if !self.record && self.played < self.event_count() as usize {
+            self.playing = true;
+        }
+    }
+    #[func]
+    fn pause(&mut self) {
+        self.playing = false;
+    }
+    #[func]
+    fn is_playing(&self) -> bool {
+        self.playing
+    }
+    /// 立即回放下一个操作，返回是否还有操作
+    #[func]
+    fn step(&mut self) -> bool {
+        let Some(event) = self
+            .recording
+            .as_ref()
+            .and_then(|r| r.events.get(self.played))
+            .copied()
+        else {
+            return false;
+        };
+        self.time = self.time.max(event.time);
+        self.apply(event.action);
+        self.played += 1;
+        if self.played == self.event_count() as usize {
+            self.playing = false;
+            self.base_mut().emit_signal("playback_finished".into(), &[]);
+        }
+        true
+    }
+    #[func]
+    fn event_count(&self) -> i32 {
+        self.recording.as_ref().map_or(0, |r| r.events.len() as i32)
+    }
+    #[func]
+    fn played_count(&self) -> i32 {
+        self.played as i32
+    }
+    /// 最后一个操作的时间
+    #[func]
+    fn duration(&self) -> f64 {
+        self.recording.as_ref().map_or(0.0, Recording::duration)
+    }
+    #[func]
+    fn on_user_moved(
+        &mut self,
+        src_pos: Vector2i,
+        direction: Direction2D,
+        count: i32,
+        _mistake: bool,
+    ) {
+        self.push(ReplayAction::Move {
+            src_pos,
+            direction,
+            count,
+        });
+    }
+    #[func]
+    fn on_move_undone(&mut self) {
+        self.push(ReplayAction::Undo);
+    }
+    #[func]
+    fn on_hint_used(&mut self) {
+        self.push(ReplayAction::Hint);
+    }
+    #[func]
+    fn on_puzzle_solved(&mut self) {
+        self.finished = true;
+    }
+    fn push(&mut self, action: ReplayAction) {
+        if self.finished {
+            return;
+        }
+        let time = self.time;
+        if let Some(recording) = self.recording.as_mut() {
+            recording.events.push(ReplayEvent { time, action });
+        }
+    }
+    fn apply(&mut self, action: ReplayAction) {
+        let Some(mut map) = self.map.clone() else {
+            return;
+        };
+        let mut map = map.bind_mut();
+        match action {
+            ReplayAction::Move {
+                src_pos,
+                direction,
+                count,
+            } => {
+                // 录像中的操作都改变过桥梁数量，无事发生说明录像和地图不一致
+                if map.user_set_bridge(src_pos, direction, count) == BridgeAction::Pass {
+                    godot_error!(
+                        "录像中的操作无法执行：{} {} {} {}",
+                        src_pos.x,
+                        src_pos.y,
+                        direction.name(),
+                        count
+                    );
+                }
+            }
+            ReplayAction::Undo => {
+                map.undo();
+            }
+            ReplayAction::Hint => {
+                map.hint();
+            }
+        }
+    }
+}
+
+#[godot_api]
+impl INode for Replay {
+    fn ready(&mut self) {
+        if !self.record {
+            return;
+        }
+        let Some(mut map) = self.map.clone() else {
+            godot_error!("Replay没有设置map");
+            return;
+        };
+        // 地图在前面的节点中已经生成好了
+        self.recording = Some(Recording {
+            puzzle: map.bind().to_puzzle(),
+            events: vec![],
+        });
+        let this = self.base().clone();
+        for (signal, method) in [
+            ("user_moved", "on_user_moved"),
+            ("move_undone", "on_move_undone"),
+            ("hint_used", "on_hint_used"),
+            ("puzzle_solved", "on_puzzle_solved"),
+        ] {
+            map.connect(signal.into(), Callable::from_object_method(&this, method));
+        }
+    }
+    fn process(&mut self, delta: f64) {
+        if self.record {
+            if !self.finished {
+                self.time += delta;
+            }
+            return;
+        }
+        if !self.playing {
+            return;
+        }
+        self.time += delta * self.speed;
+        while self
+            .recording
+            .as_ref()
+            .and_then(|r| r.events.get(self.played))
+            .is_some_and(|e| e.time <= self.time)
+        {
+            self.step();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_round_trip() {
+        let text = "size 3 1\nvariant Square\nwrap 0\nmax_bridges 2\ngrid\n2 . 2\nreplay\n0.500 move 0 0 Right 1\n1.250 move 0 0 Right 2\n2.000 undo\n3.125 hint\n";
+        let recording = Recording::from_text(text).unwrap();
+        assert_eq!(recording.puzzle.islands.len(), 2);
+        assert_eq!(
+            recording.events[1],
+            ReplayEvent {
+                time: 1.25,
+                action: ReplayAction::Move {
+                    src_pos: Vector2i::new(0, 0),
+                    direction: Direction2D::Right,
+                    count: 2
+                }
+            }
+        );
+        assert_eq!(recording.events[2].action, ReplayAction::Undo);
+        assert_eq!(recording.duration(), 3.125);
+        assert_eq!(recording.to_text(), text);
+    }
+    #[test]
+    fn test_text_error() {
+        let puzzle = "size 1 1\ngrid\n.\n";
+        assert!(
+            Recording::from_text(&format!("{}replay\n1 move 0 0 Forward 1\n", puzzle)).is_err()
+        );
+        assert!(Recording::from_text(&format!("{}replay\n2 undo\n1 undo\n", puzzle)).is_err());
+        assert!(Recording::from_text(&format!("{}replay\nundo\n", puzzle)).is_err());
+        assert!(Recording::from_text(&format!("{}replay\n1 hint\n", puzzle)).is_ok());
+        // 起点是空格子
+        let puzzle = "size 3 1\ngrid\n. 1 1\n";
+        assert!(Recording::from_text(&format!("{}replay\n1 move 0 0 Right 1\n", puzzle)).is_err());
+        assert!(Recording::from_text(&format!("{}replay\n1 move 1 0 Right 1\n", puzzle)).is_ok());
+    }
+}
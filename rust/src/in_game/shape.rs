@@ -0,0 +1,232 @@
+use super::bridge::order_vector2i;
+use super::game_map::{Direction2D, MapVariant};
+use godot::prelude::*;
+use std::collections::HashSet;
+
+/// 地图的形状：大小、网格类型、是否环形以及属于地图的格子。
+/// 生成地图和玩家搭桥都用这里的规则计算前进的格子和桥梁经过的格子
+#[derive(Debug, Clone, Copy)]
+pub struct Shape<'a> {
+    pub width: i32,
+    pub height: i32,
+    pub variant: MapVariant,
+    pub wrap: bool,
+    /// 属于地图的格子，None时为整个矩形
+    pub mask: Option<&'a HashSet<Vector2i>>,
+}
+
+impl<'a> Shape<'a> {
+    /// 矩形范围内所有的格子
+    pub fn rect_cells(&self) -> Vec<Vector2i> {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| Vector2i::new(x, y)))
+            .collect()
+    }
+    /// 地图中格子的数量
+    pub fn area(&self) -> i32 {
+        match self.mask {
+            Some(mask) => mask.len() as i32,
+            None => self.width * self.height,
+        }
+    }
+    /// 不属于地图的格子
+    pub fn holes(&self) -> Vec<Vector2i> {
+        match self.mask {
+            Some(mask) => self
+                .rect_cells()
+                .into_iter()
+                .filter(|p| !mask.contains(p))
+                .collect(),
+            None => vec![],
+        }
+    }
+    /// 把坐标映射回地图内，环形地图会从另一边出现
+    pub fn wrap_pos(&self, point: Vector2i) -> Vector2i {
+        if !self.wrap {
+            return point;
+        }
+        Vector2i::new(
+            point.x.rem_euclid(self.width),
+            point.y.rem_euclid(self.height),
+        )
+    }
+    /// 沿方向前进一格，走出地图时为None
+    pub fn next_pos(&self, point: Vector2i, direction: Direction2D) -> Option<Vector2i> {
+        let next = self.wrap_pos(point + direction.to_vector());
+        if self.is_in_bounds(&next) {
+            Some(next)
+        } else {
+            None
+        }
+    }
+    /// 从point沿方向前进一格时经过的对角线交点，见`calc_crossings`
+    pub fn crossing_key(&self, point: Vector2i, direction: Direction2D) -> Vector2i {
+        let key = point * 2 + direction.to_vector();
+        if !self.wrap {
+            return key;
+        }
+        Vector2i::new(
+            key.x.rem_euclid(self.width * 2),
+            key.y.rem_euclid(self.height * 2),
+        )
+    }
+    pub fn is_in_map(&self, point: &Vector2i) -> bool {
+        self.is_in_bounds(&self.wrap_pos(*point))
+    }
+    pub fn is_in_bounds(&self, point: &Vector2i) -> bool {
+        if point.x < 0 || point.y < 0 {
+            return false;
+        }
+        if point.x >= self.width || point.y >= self.height {
+            return false;
+        }
+        match self.mask {
+            Some(mask) => mask.contains(point),
+            None => true,
+        }
+    }
+    /// 岛屿周围相邻的格子
+    pub fn gate_pos(&self, island_pos: Vector2i) -> Vec<Vector2i> {
+        self.variant
+            .directions()
+            .iter()
+            .filter_map(|d| self.next_pos(island_pos, *d))
+            .collect()
+    }
+    pub fn calc_points(
+        from: Option<Vector2i>,
+        current: Vector2i,
+        exclude_endpoint: bool,
+    ) -> Vec<Vector2i> {
+        if from.is_none() {
+            if exclude_endpoint {
+                return vec![];
+            }
+            return vec![current];
+        }
+        let from = from.unwrap();
+        if from == current {
+            if exclude_endpoint {
+                return vec![];
+            }
+            return vec![current];
+        }
+        let (first, second) = order_vector2i(from, current);
+        let Some(direction) = Direction2D::between(first, second) else {
+            godot_error!("不可能的情况");
+            return vec![];
+        };
+        let mut res = vec![];
+        let mut p = first;
+        loop {
+            let is_endpoint = p == first || p == second;
+            if !(exclude_endpoint && is_endpoint) {
+                res.push(p);
+            }
+            if p == second {
+                break;
+            }
+            p += direction.to_vector();
+        }
+        res
+    }
+    /// 对角线桥梁每一步经过的两个格子之间的交点(用两倍坐标表示，即两个格子坐标之和)
+    ///
+    /// 两条对角线桥梁交叉时不经过同一个格子，但是会经过同一个交点；
+    /// 横竖方向的一步得到的交点一个分量为奇数一个为偶数，不会和对角线的交点重合
+    pub fn calc_crossings(from: Vector2i, current: Vector2i) -> Vec<Vector2i> {
+        let points = Self::calc_points(Some(from), current, false);
+        points
+            .windows(2)
+            .filter(|w| w[0].x != w[1].x && w[0].y != w[1].y)
+            .map(|w| w[0] + w[1])
+            .collect()
+    }
+    /// 两个岛屿之间桥梁经过的格子(不含端点)和对角线交点，环形地图会跨越边界
+    pub fn walk_link(
+        &self,
+        from: Vector2i,
+        to: Vector2i,
+        direction: Direction2D,
+    ) -> (Vec<Vector2i>, Vec<Vector2i>) {
+        if !self.wrap {
+            return (
+                Self::calc_points(Some(from), to, true),
+                Self::calc_crossings(from, to),
+            );
+        }
+        let mut points = vec![];
+        let mut crossings = vec![];
+        let mut p = from;
+        loop {
+            if direction.to_vector().x != 0 && direction.to_vector().y != 0 {
+                crossings.push(self.crossing_key(p, direction));
+            }
+            p = match self.next_pos(p, direction) {
+                Some(next) if next != from => next,
+                _ => break,
+            };
+            if p == to {
+                break;
+            }
+            points.push(p);
+        }
+        (points, crossings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calc_points() {
+        assert_eq!(
+            Shape::calc_points(Some(Vector2i::new(0, 0)), Vector2i::new(0, 2), false),
+            vec![
+                Vector2i::new(0, 0),
+                Vector2i::new(0, 1),
+                Vector2i::new(0, 2)
+            ]
+        );
+        assert_eq!(
+            Shape::calc_points(Some(Vector2i::new(0, 0)), Vector2i::new(0, 2), true),
+            vec![Vector2i::new(0, 1),]
+        );
+        // 六边形网格的斜线
+        assert_eq!(
+            Shape::calc_points(Some(Vector2i::new(2, 0)), Vector2i::new(0, 2), true),
+            vec![Vector2i::new(1, 1)]
+        );
+    }
+    #[test]
+    fn test_calc_crossings() {
+        // 两条交叉的对角线桥梁经过同一个交点
+        assert_eq!(
+            Shape::calc_crossings(Vector2i::new(0, 0), Vector2i::new(2, 2)),
+            vec![Vector2i::new(1, 1), Vector2i::new(3, 3)]
+        );
+        assert_eq!(
+            Shape::calc_crossings(Vector2i::new(0, 1), Vector2i::new(1, 0)),
+            vec![Vector2i::new(1, 1)]
+        );
+        assert!(Shape::calc_crossings(Vector2i::new(0, 0), Vector2i::new(0, 3)).is_empty());
+    }
+    #[test]
+    fn test_wrap() {
+        let shape = Shape {
+            width: 4,
+            height: 3,
+            variant: MapVariant::Square,
+            wrap: true,
+            mask: None,
+        };
+        assert_eq!(
+            shape.next_pos(Vector2i::new(3, 0), Direction2D::Right),
+            Some(Vector2i::new(0, 0))
+        );
+        let (points, _) =
+            shape.walk_link(Vector2i::new(3, 0), Vector2i::new(1, 0), Direction2D::Right);
+        assert_eq!(points, vec![Vector2i::new(0, 0)]);
+    }
+}
@@ -0,0 +1,358 @@
+use super::game_map::Direction2D;
+use super::puzzle::Puzzle;
+use godot::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+/// 搜索的步数上限，超过时放弃
+const MAX_STEPS: usize = 200_000;
+
+/// 两个岛屿之间可以搭桥的一条连线
+#[derive(Debug)]
+struct Link {
+    /// 两端岛屿在`Puzzle::islands`中的下标
+    a: usize,
+    b: usize,
+    /// 从a出发的方向
+    direction: Direction2D,
+    /// 最多可以搭几座桥
+    max: i32,
+    /// 和这条连线交叉的连线
+    conflicts: Vec<usize>,
+}
+
+/// 谜题求解器，回溯搜索每条连线上的桥梁数量
+pub struct Solver<'a> {
+    puzzle: &'a Puzzle,
+    links: Vec<Link>,
+    /// 每条连线当前的桥梁数量
+    values: Vec<i32>,
+    /// 每条连线至少要搭的桥梁数量，见`solve_containing`
+    minimums: Vec<i32>,
+    /// 每个岛屿当前的桥梁数量
+    sums: Vec<i32>,
+    /// 每个岛屿还没有决定的连线最多可以再搭几座桥
+    remaining: Vec<i32>,
+    solutions: usize,
+    /// 找到的第一个解
+    first_solution: Option<Vec<i32>>,
+    steps: usize,
+}
+
+impl<'a> Solver<'a> {
+    pub fn new(puzzle: &'a Puzzle) -> Self {
+        let links = Self::build_links(puzzle);
+        let mut remaining = vec![0; puzzle.islands.len()];
+        for link in links.iter() {
+            remaining[link.a] += link.max;
+            remaining[link.b] += link.max;
+        }
+        Solver {
+            puzzle,
+            values: vec![0; links.len()],
+            minimums: vec![0; links.len()],
+            sums: vec![0; puzzle.islands.len()],
+            remaining,
+            links,
+            solutions: 0,
+            first_solution: None,
+            steps: 0,
+        }
+    }
+    /// 统计解的数量，最多统计到limit个，超过步数上限时为None
+    pub fn count_solutions(puzzle: &Puzzle, limit: usize) -> Option<usize> {
+        let mut solver = Solver::new(puzzle);
+        if (0..puzzle.islands.len()).all(|i| solver.is_feasible(i)) {
+            solver.search(0, limit);
+        }
+        if solver.steps > MAX_STEPS {
+            return None;
+        }
+        Some(solver.solutions)
+    }
+    /// 求出一个解，每条连线为(起点岛屿位置, 方向, 桥梁数量)，包括没有桥的连线
+    pub fn solve(puzzle: &Puzzle) -> Option<Vec<(Vector2i, Direction2D, i32)>> {
+        Solver::new(puzzle).first()
+    }
+    /// 求出一个包含已有桥梁的解：每条连线的桥梁数量不少于bridges中的数量，
+    /// bridges的格式和解相同，同一条连线可以从两端各出现一次。
+    /// 外层None表示太复杂，内层None表示无解，用于判断多解的谜题中的操作是否出错
+    pub fn solve_containing(
+        puzzle: &Puzzle,
+        bridges: &[(Vector2i, Direction2D, i32)],
+    ) -> Option<Option<Vec<(Vector2i, Direction2D, i32)>>> {
+        let mut solver = Solver::new(puzzle);
+        for (pos, direction, count) in bridges.iter() {
+            let found = solver.links.iter().position(|link| {
+                let (a, b) = (puzzle.islands[link.a].pos, puzzle.islands[link.b].pos);
+                (a == *pos && link.direction == *direction)
+                    || (b == *pos && link.direction.opposite() == *direction)
+            });
+            match found {
+                Some(i) => solver.minimums[i] = solver.minimums[i].max(*count),
+                // 不是可以搭桥的连线
+                None if *count > 0 => return Some(None),
+                None => {}
+            }
+        }
+        let res = solver.first();
+        if solver.steps > MAX_STEPS {
+            return None;
+        }
+        Some(res)
+    }
+    /// 搜索第一个解
+    fn first(&mut self) -> Option<Vec<(Vector2i, Direction2D, i32)>> {
+        if (0..self.puzzle.islands.len()).all(|i| self.is_feasible(i)) {
+            self.search(0, 1);
+        }
+        let values = self.first_solution.clone()?;
+        Some(
+            self.links
+                .iter()
+                .zip(values)
+                .map(|(link, v)| (self.puzzle.islands[link.a].pos, link.direction, v))
+                .collect(),
+        )
+    }
+    /// 用于编辑器的检查结果：(解的数量, 说明)，解的数量最多统计到2，太复杂时为-1
+    pub fn describe(puzzle: &Puzzle) -> (i32, &'static str) {
+        if puzzle.islands.is_empty() {
+            return (0, "没有岛屿");
+        }
+        if puzzle.islands.iter().any(|i| !i.hidden && i.count == 0) {
+            return (0, "有岛屿需要的桥梁数量为0");
+        }
+        match Self::count_solutions(puzzle, 2) {
+            None => (-1, "太复杂，无法判断"),
+            Some(0) => (0, "无解"),
+            Some(1) => (1, "唯一解"),
+            Some(_) => (2, "有多个解"),
+        }
+    }
+    /// 找出所有可以搭桥的连线以及它们之间的交叉关系
+    fn build_links(puzzle: &Puzzle) -> Vec<Link> {
+        let index: HashMap<Vector2i, usize> = puzzle
+            .islands
+            .iter()
+            .enumerate()
+            .map(|(i, island)| (island.pos, i))
+            .collect();
+        let blocked: HashSet<Vector2i> = puzzle
+            .obstacles
+            .iter()
+            .chain(puzzle.holes.iter())
+            .copied()
+            .collect();
+        let wrap = |p: Vector2i, w: i32, h: i32| {
+            if puzzle.wrap {
+                Vector2i::new(p.x.rem_euclid(w), p.y.rem_euclid(h))
+            } else {
+                p
+            }
+        };
+        let (w, h) = (puzzle.width, puzzle.height);
+        let max_of = |i: usize| {
+            let island = &puzzle.islands[i];
+            if island.hidden {
+                puzzle.max_bridges_per_link
+            } else {
+                island.count.min(puzzle.max_bridges_per_link)
+            }
+        };
+        let mut links = vec![];
+        // 每条连线经过的格子和对角线交点，见`Shape::calc_crossings`
+        let mut paths: Vec<(HashSet<Vector2i>, HashSet<Vector2i>)> = vec![];
+        for (a, island) in puzzle.islands.iter().enumerate() {
+            for direction in puzzle.variant.directions().iter() {
+                let step = direction.to_vector();
+                let mut cells = HashSet::new();
+                let mut crossings = HashSet::new();
+                let mut p = island.pos;
+                loop {
+                    if step.x != 0 && step.y != 0 {
+                        crossings.insert(wrap(p * 2 + step, w * 2, h * 2));
+                    }
+                    let next = wrap(p + step, w, h);
+                    if next.x < 0 || next.y < 0 || next.x >= w || next.y >= h {
+                        break;
+                    }
+                    if next == island.pos || blocked.contains(&next) {
+                        break;
+                    }
+                    if let Some(&b) = index.get(&next) {
+                        // 另一个方向会再找到一次，只保留一次
+                        if b > a && puzzle.islands[b].color_id == island.color_id {
+                            links.push(Link {
+                                a,
+                                b,
+                                direction: *direction,
+                                max: max_of(a).min(max_of(b)),
+                                conflicts: vec![],
+                            });
+                            paths.push((cells, crossings));
+                        }
+                        break;
+                    }
+                    cells.insert(next);
+                    p = next;
+                }
+            }
+        }
+        for i in 0..links.len() {
+            for j in 0..links.len() {
+                if i != j
+                    && (!paths[i].0.is_disjoint(&paths[j].0)
+                        || !paths[i].1.is_disjoint(&paths[j].1))
+                {
+                    links[i].conflicts.push(j);
+                }
+            }
+        }
+        links
+    }
+    fn search(&mut self, i: usize, limit: usize) {
+        if self.solutions >= limit || self.steps > MAX_STEPS {
+            return;
+        }
+        self.steps += 1;
+        if i == self.links.len() {
+            if self.is_connected() {
+                if self.first_solution.is_none() {
+                    self.first_solution = Some(self.values.clone());
+                }
+                self.solutions += 1;
+            }
+            return;
+        }
+        let (a, b, max) = (self.links[i].a, self.links[i].b, self.links[i].max);
+        // 已经有交叉的桥梁时只能不搭
+        let blocked = self.links[i]
+            .conflicts
+            .iter()
+            .any(|j| *j < i && self.values[*j] > 0);
+        let top = if blocked { 0 } else { max };
+        self.remaining[a] -= max;
+        self.remaining[b] -= max;
+        for v in self.minimums[i]..=top {
+            self.values[i] = v;
+            self.sums[a] += v;
+            self.sums[b] += v;
+            if self.is_feasible(a) && self.is_feasible(b) {
+                self.search(i + 1, limit);
+            }
+            self.sums[a] -= v;
+            self.sums[b] -= v;
+        }
+        self.values[i] = 0;
+        self.remaining[a] += max;
+        self.remaining[b] += max;
+    }
+    /// 岛屿的桥梁数量是否还可能满足要求，隐藏数量的岛屿没有要求
+    fn is_feasible(&self, island: usize) -> bool {
+        let info = &self.puzzle.islands[island];
+        if info.hidden {
+            return true;
+        }
+        let sum = self.sums[island];
+        sum <= info.count && sum + self.remaining[island] >= info.count
+    }
+    /// 每种颜色的岛屿是否各自连成一个网络
+    fn is_connected(&self) -> bool {
+        let n = self.puzzle.islands.len();
+        let mut neighbors = vec![vec![]; n];
+        for (i, link) in self.links.iter().enumerate() {
+            if self.values[i] > 0 {
+                neighbors[link.a].push(link.b);
+                neighbors[link.b].push(link.a);
+            }
+        }
+        let mut visited = vec![false; n];
+        let mut networks = HashSet::new();
+        for start in 0..n {
+            if visited[start] {
+                continue;
+            }
+            if !networks.insert(self.puzzle.islands[start].color_id) {
+                return false;
+            }
+            visited[start] = true;
+            let mut stack = vec![start];
+            while let Some(current) = stack.pop() {
+                for next in neighbors[current].iter() {
+                    if !visited[*next] {
+                        visited[*next] = true;
+                        stack.push(*next);
+                    }
+                }
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(text: &str) -> Option<usize> {
+        Solver::count_solutions(&Puzzle::from_text(text).unwrap(), 2)
+    }
+
+    #[test]
+    fn test_count_solutions() {
+        assert_eq!(count("size 3 1\ngrid\n2 . 2\n"), Some(1));
+        assert_eq!(count("size 3 1\ngrid\n? . 2\n"), Some(1));
+        assert_eq!(count("size 3 1\ngrid\n2 # 2\n"), Some(0));
+        // 四个角都是2时只有绕一圈的单桥是连通的
+        assert_eq!(count("size 2 2\ngrid\n2 2\n2 2\n"), Some(1));
+        assert_eq!(count("size 2 2\ngrid\n? ?\n? ?\n"), Some(2));
+        // 环形地图中两个岛屿可以从两边相连
+        assert_eq!(count("size 4 1\nwrap 1\ngrid\n2 . 2 .\n"), Some(2));
+    }
+    #[test]
+    fn test_solve() {
+        let puzzle = Puzzle::from_text("size 3 2\ngrid\n3 . 2\n1 . .\n").unwrap();
+        assert_eq!(
+            Solver::solve(&puzzle),
+            Some(vec![
+                (Vector2i::new(0, 0), Direction2D::Right, 2),
+                (Vector2i::new(0, 0), Direction2D::Down, 1)
+            ])
+        );
+    }
+    #[test]
+    fn test_solve_containing() {
+        // 两个解：上下两条连线为两座桥、左右为一座，或者相反
+        let puzzle = Puzzle::from_text("size 3 3\ngrid\n3 . 3\n. . .\n3 . 3\n").unwrap();
+        assert_eq!(Solver::count_solutions(&puzzle, 3), Some(2));
+        let p = Vector2i::new(0, 0);
+        let res = Solver::solve_containing(&puzzle, &[(p, Direction2D::Right, 2)]);
+        assert!(res.unwrap().unwrap().contains(&(p, Direction2D::Right, 2)));
+        // 从另一端出发的同一条连线
+        let q = Vector2i::new(0, 2);
+        let res = Solver::solve_containing(&puzzle, &[(q, Direction2D::Up, 2)]);
+        assert!(res.unwrap().unwrap().contains(&(p, Direction2D::Down, 2)));
+        // 两边都是两座桥时岛屿需要4座
+        let bridges = [(p, Direction2D::Right, 2), (p, Direction2D::Down, 2)];
+        assert_eq!(Solver::solve_containing(&puzzle, &bridges), Some(None));
+        // 不是连线
+        let bridges = [(Vector2i::new(1, 1), Direction2D::Right, 1)];
+        assert_eq!(Solver::solve_containing(&puzzle, &bridges), Some(None));
+    }
+    #[test]
+    fn test_describe() {
+        let describe = |text: &str| Solver::describe(&Puzzle::from_text(text).unwrap()).0;
+        assert_eq!(describe("size 3 1\ngrid\n. . .\n"), 0);
+        assert_eq!(describe("size 3 1\ngrid\n0 . 1\n"), 0);
+        assert_eq!(describe("size 3 1\ngrid\n1 . 1\n"), 1);
+        assert_eq!(describe("size 2 2\ngrid\n? ?\n? ?\n"), 2);
+    }
+    #[test]
+    fn test_crossing_bridges() {
+        // 对角线变体中两条交叉的对角线桥梁不能同时存在
+        let text = "size 3 3\nvariant Diagonal\ngrid\n1 # .\n# . #\n. # 1\n";
+        assert_eq!(count(text), Some(1));
+        let text = "size 3 3\nvariant Diagonal\ngrid\n1 # 1:1\n# . #\n1:1 # 1\n";
+        assert_eq!(count(text), Some(0));
+    }
+}
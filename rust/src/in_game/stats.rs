@@ -0,0 +1,388 @@
+use super::game_map::{Direction2D, GameMap};
+use super::game_session::GameSession;
+use godot::engine::ConfigFile;
+use godot::prelude::*;
+use std::collections::BTreeMap;
+
+/// 记录连胜等信息的section，其他section都是`宽x高-难度`
+const STREAK_SECTION: &'static str = "streak";
+
+/// 一种地图大小和难度的统计
+#[derive(Debug, Clone, Default, PartialEq)]
+struct GroupStats {
+    started: i32,
+    finished: i32,
+    /// 所有完成的谜题的总用时(秒)
+    total_time: f64,
+    best_time: Option<f64>,
+    moves: i32,
+    hints: i32,
+    mistakes: i32,
+}
+
+impl GroupStats {
+    fn average_time(&self) -> Option<f64> {
+        if self.finished == 0 {
+            None
+        } else {
+            Some(self.total_time / self.finished as f64)
+        }
+    }
+    fn merge(&mut self, other: &GroupStats) {
+        self.started += other.started;
+        self.finished += other.finished;
+        self.total_time += other.total_time;
+        self.best_time = match (self.best_time, other.best_time) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.moves += other.moves;
+        self.hints += other.hints;
+        self.mistakes += other.mistakes;
+    }
+}
+
+/// (宽, 高, 难度)
+type GroupKey = (i32, i32, i32);
+
+/// 一个玩家的所有统计
+#[derive(Debug, Default, PartialEq)]
+struct PlayerStats {
+    groups: BTreeMap<GroupKey, GroupStats>,
+    /// 正在进行的谜题
+    current: Option<GroupKey>,
+    /// 连续完成的谜题数量，中途放弃时清零
+    current_streak: i32,
+    best_streak: i32,
+}
+
+impl PlayerStats {
+    fn record_start(&mut self, key: GroupKey) {
+        if self.current.is_some() {
+            self.current_streak = 0;
+        }
+        self.groups.entry(key).or_default().started += 1;
+        self.current = Some(key);
+    }
+    fn current_group(&mut self) -> Option<&mut GroupStats> {
+        let key = self.current?;
+        self.groups.get_mut(&key)
+    }
+    fn record_move(&mut self, mistake: bool) {
+        if let Some(group) = self.current_group() {
+            group.moves += 1;
+            if mistake {
+                group.mistakes += 1;
+            }
+        }
+    }
+    fn record_hint(&mut self) {
+        if let Some(group) = self.current_group() {
+            group.hints += 1;
+        }
+    }
+    fn record_finish(&mut self, seconds: f64) {
+        let Some(group) = self.current_group() else {
+            return;
+        };
+        group.finished += 1;
+        group.total_time += seconds;
+        group.best_time = Some(group.best_time.map_or(seconds, |t| t.min(seconds)));
+        self.current = None;
+        self.current_streak += 1;
+        self.best_streak = self.best_streak.max(self.current_streak);
+    }
+    fn total(&self) -> GroupStats {
+        let mut total = GroupStats::default();
+        for group in self.groups.values() {
+            total.merge(group);
+        }
+        total
+    }
+}
+
+fn section_name(key: GroupKey) -> String {
+    format!("{}x{}-{}", key.0, key.1, key.2)
+}
+
+fn parse_section_name(name: &str) -> Option<GroupKey> {
+    let (size, game_mode) = name.split_once('-')?;
+    let (width, height) = size.split_once('x')?;
+    Some((
+        width.parse().ok()?,
+        height.parse().ok()?,
+        game_mode.parse().ok()?,
+    ))
+}
+
+/// 玩家统计：从`GameMap`的信号中记录开始、操作、提示和完成，按照profile保存在`user://`中
+///
+/// 没有设置map或者`recording`为false时只用于查询
+#[derive(GodotClass, Debug)]
+#[class(init, base = Node)]
+pub struct Stats {
+    /// 玩家名，见`Settings`中的`profile`，在进入场景树之前设置
+    #[init(default = "default".into())]
+    #[export]
+    profile: GString,
+    #[export]
+    map: Option<Gd<GameMap>>,
+    /// 用于读取完成用时
+    #[export]
+    session: Option<Gd<GameSession>>,
+    /// 是否记录这局游戏，只统计普通的单人游戏，同屏对战、编辑器试玩、每日谜题和闯关不计入，
+    /// 在进入场景树之前设置
+    #[init(default = true)]
+    #[export]
+    recording: bool,
+    stats: PlayerStats,
+    /// 已经读取的profile
+    loaded_profile: Option<GString>,
+    base: Base<Node>,
+}
+
+#[godot_api]
+impl Stats {
+    /// 重新读取当前profile的统计
+    #[func]
+    fn reload(&mut self) {
+        let mut config = ConfigFile::new_gd();
+        // 文件不存在时为空记录
+        config.load(self.record_path());
+        let get = |section: &GString, key: &str| {
+            if config.has_section_key(section.clone(), key.into()) {
+                config.get_value(section.clone(), key.into())
+            } else {
+                Variant::nil()
+            }
+        };
+        let get_i32 = |section: &GString, key: &str| get(section, key).try_to::<i32>().unwrap_or(0);
+        let get_f64 =
+            |section: &GString, key: &str| get(section, key).try_to::<f64>().unwrap_or(-1.0);
+        let mut stats = PlayerStats::default();
+        for section in config.get_sections().as_slice() {
+            if section.to_string() == STREAK_SECTION {
+                stats.current_streak = get_i32(section, "current");
+                stats.best_streak = get_i32(section, "best");
+                stats.current = parse_section_name(&get(section, "in_progress").to_string());
+                continue;
+            }
+            let Some(key) = parse_section_name(&section.to_string()) else {
+                continue;
+            };
+            let best_time = get_f64(section, "best_time");
+            stats.groups.insert(
+                key,
+                GroupStats {
+                    started: get_i32(section, "started"),
+                    finished: get_i32(section, "finished"),
+                    total_time: get_f64(section, "total_time").max(0.0),
+                    best_time: if best_time < 0.0 {
+                        None
+                    } else {
+                        Some(best_time)
+                    },
+                    moves: get_i32(section, "moves"),
+                    hints: get_i32(section, "hints"),
+                    mistakes: get_i32(section, "mistakes"),
+                },
+            );
+        }
+        self.stats = stats;
+        self.loaded_profile = Some(self.profile.clone());
+    }
+    #[func]
+    fn save(&mut self) {
+        self.ensure_loaded();
+        let mut config = ConfigFile::new_gd();
+        for (key, group) in self.stats.groups.iter() {
+            let section: GString = section_name(*key).into();
+            let values = [
+                ("started", Variant::from(group.started)),
+                ("finished", Variant::from(group.finished)),
+                ("total_time", Variant::from(group.total_time)),
+                ("best_time", Variant::from(group.best_time.unwrap_or(-1.0))),
+                ("moves", Variant::from(group.moves)),
+                ("hints", Variant::from(group.hints)),
+                ("mistakes", Variant::from(group.mistakes)),
+            ];
+            for (k, v) in values {
+                config.set_value(section.clone(), k.into(), v);
+            }
+        }
+        config.set_value(
+            STREAK_SECTION.into(),
+            "current".into(),
+            Variant::from(self.stats.current_streak),
+        );
+        config.set_value(
+            STREAK_SECTION.into(),
+            "best".into(),
+            Variant::from(self.stats.best_streak),
+        );
+        // 保存正在进行的谜题，重新启动之后放弃也会清零连胜
+        let in_progress = self.stats.current.map(section_name).unwrap_or_default();
+        config.set_value(
+            STREAK_SECTION.into(),
+            "in_progress".into(),
+            GString::from(in_progress).to_variant(),
+        );
+        config.save(self.record_path());
+    }
+    /// 按照(宽, 高, 难度)排序的分组数量
+    #[func]
+    fn group_count(&mut self) -> i32 {
+        self.ensure_loaded();
+        self.stats.groups.len() as i32
+    }
+    /// 第index个分组的统计，见`to_dict`
+    #[func]
+    fn group_stats(&mut self, index: i32) -> Dictionary {
+        self.ensure_loaded();
+        let Some((key, group)) = usize::try_from(index)
+            .ok()
+            .and_then(|i| self.stats.groups.iter().nth(i))
+        else {
+            return Dictionary::new();
+        };
+        let mut dict = Self::to_dict(group);
+        dict.set("width", key.0);
+        dict.set("height", key.1);
+        dict.set("game_mode", key.2);
+        dict
+    }
+    /// 所有分组合计的统计
+    #[func]
+    fn total_stats(&mut self) -> Dictionary {
+        self.ensure_loaded();
+        Self::to_dict(&self.stats.total())
+    }
+    #[func]
+    fn current_streak(&mut self) -> i32 {
+        self.ensure_loaded();
+        self.stats.current_streak
+    }
+    #[func]
+    fn best_streak(&mut self) -> i32 {
+        self.ensure_loaded();
+        self.stats.best_streak
+    }
+    #[func]
+    fn on_map_generated(&mut self, width: i32, height: i32, game_mode: i32) {
+        self.ensure_loaded();
+        self.stats.record_start((width, height, game_mode));
+        self.save();
+    }
+    #[func]
+    fn on_user_moved(
+        &mut self,
+        _src_pos: Vector2i,
+        _direction: Direction2D,
+        _count: i32,
+        mistake: bool,
+    ) {
+        self.ensure_loaded();
+        self.stats.record_move(mistake);
+    }
+    #[func]
+    fn on_hint_used(&mut self) {
+        self.ensure_loaded();
+        self.stats.record_hint();
+        self.save();
+    }
+    #[func]
+    fn on_puzzle_solved(&mut self) {
+        self.ensure_loaded();
+        // 信号处理中不能借用地图，但是可以读取GameSession的用时
+        let seconds = match &self.session {
+            Some(session) => session.get("elapsed".into()).try_to::<f64>().unwrap_or(0.0),
+            None => 0.0,
+        };
+        self.stats.record_finish(seconds);
+        self.save();
+    }
+    /// -1表示没有记录
+    fn to_dict(group: &GroupStats) -> Dictionary {
+        dict! {
+            "started": group.started,
+            "finished": group.finished,
+            "best_time": group.best_time.unwrap_or(-1.0),
+            "average_time": group.average_time().unwrap_or(-1.0),
+            "moves": group.moves,
+            "hints": group.hints,
+            "mistakes": group.mistakes,
+        }
+    }
+    fn record_path(&self) -> GString {
+        format!("user://stats_{}.cfg", self.profile).into()
+    }
+    /// 修改profile之后第一次使用时读取
+    fn ensure_loaded(&mut self) {
+        if self.loaded_profile.as_ref() != Some(&self.profile) {
+            self.reload();
+        }
+    }
+}
+
+#[godot_api]
+impl INode for Stats {
+    fn ready(&mut self) {
+        let Some(mut map) = self.map.clone().filter(|_| self.recording) else {
+            return;
+        };
+        let this = self.base().clone();
+        for (signal, method) in [
+            ("map_generated", "on_map_generated"),
+            ("user_moved", "on_user_moved"),
+            ("hint_used", "on_hint_used"),
+            ("puzzle_solved", "on_puzzle_solved"),
+        ] {
+            map.connect(signal.into(), Callable::from_object_method(&this, method));
+        }
+    }
+    fn exit_tree(&mut self) {
+        // 操作次数只在退出时保存
+        if self.map.is_some() && self.recording && self.loaded_profile.is_some() {
+            self.save();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let mut stats = PlayerStats::default();
+        stats.record_start((10, 7, 1));
+        stats.record_move(false);
+        stats.record_move(true);
+        stats.record_hint();
+        stats.record_finish(30.0);
+        stats.record_start((10, 7, 1));
+        stats.record_finish(20.0);
+        let group = &stats.groups[&(10, 7, 1)];
+        assert_eq!(group.started, 2);
+        assert_eq!(group.finished, 2);
+        assert_eq!(group.best_time, Some(20.0));
+        assert_eq!(group.average_time(), Some(25.0));
+        assert_eq!((group.moves, group.mistakes, group.hints), (2, 1, 1));
+        assert_eq!(stats.current_streak, 2);
+        // 放弃之后连胜清零
+        stats.record_start((5, 5, 2));
+        stats.record_start((5, 5, 2));
+        assert_eq!(stats.current_streak, 0);
+        assert_eq!(stats.best_streak, 2);
+        stats.record_finish(10.0);
+        let total = stats.total();
+        assert_eq!((total.started, total.finished), (4, 3));
+        assert_eq!(total.best_time, Some(10.0));
+    }
+    #[test]
+    fn test_section_name() {
+        assert_eq!(section_name((10, 7, 3)), "10x7-3");
+        assert_eq!(parse_section_name("10x7-3"), Some((10, 7, 3)));
+        assert_eq!(parse_section_name("streak"), None);
+    }
+}
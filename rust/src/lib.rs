@@ -1,5 +1,5 @@
 mod common;
-mod in_game;
+pub mod in_game;
 #[macro_use]
 mod macros;
 